@@ -0,0 +1,215 @@
+use crate::config::BackupConfig;
+use crate::error::{Context, Result};
+use crate::file_finder::{FileFinder, SearchOptions};
+use chrono::{DateTime, Local};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// A zip of every matching file for a backup with `bundle = true`, written
+/// to a private temp directory that is removed again when dropped.
+#[derive(Debug)]
+pub struct Bundle {
+    pub path: PathBuf,
+    /// Modified time of the newest bundled file.
+    pub modified: DateTime<Local>,
+    pub size: u64,
+    /// Number of files in the archive.
+    pub files: usize,
+    /// Holds the archive; removed with it on drop.
+    _directory: TempDir,
+}
+
+impl Bundle {
+    /// Zips every file of `backup` that passes `options` into
+    /// `{name}-{date}.zip`, dated by the newest file. Entries are named
+    /// relative to their source directory. `None` when nothing matches.
+    pub fn create(backup: &BackupConfig, options: &SearchOptions) -> Result<Option<Self>> {
+        let files = FileFinder::find_all_files_in_dirs(
+            backup.source_directory.as_slice(),
+            backup.file_pattern.as_slice(),
+            options,
+        )
+        .context("Failed to search for files")?;
+        let Some((_, modified, _)) = files.last() else {
+            return Ok(None);
+        };
+
+        // A fresh directory per bundle, so concurrent backups can't clash and
+        // other users can't read or replace the archive
+        let directory = tempfile::Builder::new()
+            .prefix("latest-sender-bundle-")
+            .tempdir()
+            .context("Failed to create bundle directory")?;
+        let path = directory
+            .path()
+            .join(bundle_file_name(&backup.name, *modified));
+        let mut bundle = Self {
+            modified: *modified,
+            size: 0,
+            files: files.len(),
+            path,
+            _directory: directory,
+        };
+
+        let entries: Vec<(String, &Path)> = {
+            let mut taken = HashSet::new();
+            files
+                .iter()
+                .map(|(file, ..)| {
+                    let directory = backup.source_directory.containing(file);
+                    let mut name = entry_name(file, Path::new(directory));
+                    // Same relative name in two source directories
+                    if !taken.insert(name.clone()) {
+                        name = file.to_string_lossy().trim_start_matches('/').to_string();
+                    }
+                    (name, file.as_path())
+                })
+                .collect()
+        };
+        write_zip(&bundle.path, &entries)
+            .with_context(|| format!("Failed to create bundle {:?}", bundle.path))?;
+
+        bundle.size = fs::metadata(&bundle.path)
+            .with_context(|| format!("Failed to read size of {:?}", bundle.path))?
+            .len();
+        Ok(Some(bundle))
+    }
+}
+
+/// `{name}-{YYYY-MM-DD}.zip`, with characters that don't belong in a file
+/// name replaced by `_`.
+pub fn bundle_file_name(name: &str, date: DateTime<Local>) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{name}-{}.zip", date.format("%Y-%m-%d"))
+}
+
+/// `file`'s path below `directory` with `/` separators, or its file name
+/// when it is elsewhere.
+fn entry_name(file: &Path, directory: &Path) -> String {
+    match file.strip_prefix(directory) {
+        Ok(relative) => relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        Err(_) => file
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
+
+fn write_zip(path: &Path, entries: &[(String, &Path)]) -> Result<()> {
+    let mut writer = zip::ZipWriter::new(File::create(path)?);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .large_file(true);
+    for (name, file) in entries {
+        writer
+            .start_file(name.as_str(), options)
+            .with_context(|| format!("Failed to add {name}"))?;
+        let mut reader = File::open(file).with_context(|| format!("Failed to open {file:?}"))?;
+        io::copy(&mut reader, &mut writer).with_context(|| format!("Failed to read {file:?}"))?;
+    }
+    writer
+        .finish()
+        .context("Failed to finish archive")?
+        .flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Patterns;
+    use chrono::TimeZone;
+    use std::io::Read;
+
+    #[test]
+    fn test_create_bundle() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir(temp_dir.path().join("eu"))?;
+        fs::write(temp_dir.path().join("db.sql"), "db dump")?;
+        fs::write(temp_dir.path().join("eu/db.sql"), "eu dump")?;
+        fs::write(temp_dir.path().join("notes.txt"), "not matched")?;
+        let backup = BackupConfig {
+            name: "bundle test".to_string(),
+            source_directory: temp_dir.path().to_str().unwrap().into(),
+            file_pattern: Patterns::Single("*.sql".to_string()),
+            recursive: true,
+            ..Default::default()
+        };
+
+        let bundle = Bundle::create(&backup, &backup.search_options()?)?.expect("files match");
+        assert_eq!(bundle.files, 2);
+        assert_eq!(
+            bundle.path.file_name().unwrap().to_string_lossy(),
+            bundle_file_name("bundle test", bundle.modified)
+        );
+        assert_eq!(bundle.size, fs::metadata(&bundle.path)?.len());
+
+        let mut archive = zip::ZipArchive::new(File::open(&bundle.path)?)?;
+        let mut names: Vec<_> = archive.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(names, ["db.sql", "eu/db.sql"]);
+        let mut content = String::new();
+        archive.by_name("eu/db.sql")?.read_to_string(&mut content)?;
+        assert_eq!(content, "eu dump");
+
+        let path = bundle.path.clone();
+        let directory = path.parent().unwrap().to_path_buf();
+        assert_ne!(directory, std::env::temp_dir());
+        drop(bundle);
+        assert!(!path.exists());
+        assert!(!directory.exists());
+
+        let nothing = BackupConfig {
+            file_pattern: Patterns::Single("*.tar".to_string()),
+            ..backup
+        };
+        assert!(Bundle::create(&nothing, &nothing.search_options()?)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundles_with_same_file_name_do_not_clash() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("db.sql"), "db dump")?;
+        let backup = |name: &str| BackupConfig {
+            name: name.to_string(),
+            source_directory: temp_dir.path().to_str().unwrap().into(),
+            file_pattern: Patterns::Single("*.sql".to_string()),
+            ..Default::default()
+        };
+        let first = backup("eu/logs");
+        let second = backup("eu logs");
+
+        let a = Bundle::create(&first, &first.search_options()?)?.expect("files match");
+        let b = Bundle::create(&second, &second.search_options()?)?.expect("files match");
+        assert_eq!(a.path.file_name(), b.path.file_name());
+        assert_ne!(a.path, b.path);
+
+        drop(a);
+        assert!(b.path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundle_file_name() {
+        let date = Local.with_ymd_and_hms(2024, 3, 9, 12, 0, 0).unwrap();
+        assert_eq!(bundle_file_name("db", date), "db-2024-03-09.zip");
+        assert_eq!(bundle_file_name("eu/logs", date), "eu_logs-2024-03-09.zip");
+    }
+}
@@ -0,0 +1,55 @@
+use crate::error::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Returns the lowercase hex SHA-256 of the file, reading it in chunks so
+/// large backups are never held in memory.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {path:?}"))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).with_context(|| format!("Failed to read {path:?}"))?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Returns the lowercase hex SHA-256 of `value`.
+pub fn sha256_str(value: &str) -> String {
+    to_hex(&Sha256::digest(value.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sha256_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("backup.sql");
+        std::fs::write(&path, "hello world")?;
+
+        assert_eq!(
+            sha256_file(&path)?,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sha256_empty_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("empty");
+        std::fs::write(&path, "")?;
+
+        assert_eq!(
+            sha256_file(&path)?,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        Ok(())
+    }
+}
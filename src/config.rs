@@ -1,144 +1,2444 @@
-use anyhow::{anyhow, Result};
-use chrono::Duration;
+use crate::discord_sender::{
+    AllowedMentions, ArchiveFormat, Embed, LongMessage, RetryPolicy, SendOptions,
+    DEFAULT_EMBED_COLOR, DEFAULT_MAX_FILE_SIZE, DEFAULT_TIMEOUT, FLAG_SUPPRESS_EMBEDS,
+    FLAG_SUPPRESS_NOTIFICATIONS,
+};
+use crate::error::{bail_config, Context, Result, SenderError};
+use crate::file_finder::{compile_pattern, SearchOptions, SelectionStrategy, DEFAULT_IGNORE_FILE};
+use crate::hooks::AfterSend;
+use crate::state::Dedup;
+use crate::template::{
+    render_path, validate_template, MESSAGE_PLACEHOLDERS, UPLOAD_FILENAME_PLACEHOLDERS,
+};
+use chrono::{DateTime, Duration, Local};
+use encoding_rs::{Encoding, UTF_8};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Commented example config written by `latest-sender init`.
+pub const CONFIG_TEMPLATE: &str = include_str!("config_template.toml");
+
+/// Delay before re-scanning when the selected file disappeared.
+pub const DEFAULT_OPEN_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
+    /// Discord webhook that gets one summary message at the end of each run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary_webhook: Option<String>,
     pub backups: Vec<BackupConfig>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BackupConfig {
-    pub name: String,
-    pub source_directory: String,
-    pub file_pattern: String,
-    pub webhook_url: String,
-    pub check_period: Option<String>,
-}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BackupConfig {
+    pub name: String,
+    /// Directory to search, or a list of them to pick the file from all.
+    pub source_directory: SourceDirectories,
+    pub file_pattern: Patterns,
+    /// Set to false to keep the definition without running it.
+    pub enabled: Option<bool>,
+    /// Discord webhook URL, or a list of them to post the file to each. For
+    /// Slack and Telegram, optionally overrides the API endpoint.
+    #[serde(default, skip_serializing_if = "WebhookUrls::is_empty")]
+    pub webhook_url: WebhookUrls,
+    /// File containing the webhook URL, read at load time instead of
+    /// `webhook_url`. A relative path is resolved against the config file's
+    /// directory.
+    pub webhook_url_file: Option<String>,
+    pub check_period: Option<String>,
+    /// Only send files modified after this RFC 3339 timestamp.
+    pub newer_than: Option<String>,
+    /// Send at most once per this interval (e.g. "1d"), tracked in the state file.
+    pub once_per: Option<String>,
+    /// How the file is picked among the matches: "mtime" (newest, the
+    /// default), "largest" or "smallest".
+    pub select_by: Option<String>,
+    /// When a file counts as already sent: "mtime" (same path and modified
+    /// time, the default), "path", "hash" (same contents) or "none".
+    pub dedup: Option<String>,
+    #[serde(default)]
+    pub exclude_patterns: Vec<String>,
+    /// Also search subdirectories of `source_directory`.
+    #[serde(default)]
+    pub recursive: bool,
+    /// Follow symlinks to their targets (default). When false, symlinks are skipped.
+    pub follow_symlinks: Option<bool>,
+    /// Skip files smaller than this many bytes, e.g. empty dumps from failed jobs.
+    pub min_size_bytes: Option<u64>,
+    /// Fall back to the next-newest file when the newest can't be read.
+    #[serde(default)]
+    pub skip_unreadable: bool,
+    /// Match `file_pattern` and `exclude_patterns` ignoring case.
+    #[serde(default)]
+    pub case_insensitive: bool,
+    /// Gitignore-style file in `source_directory`; defaults to `.senderignore`.
+    /// An empty string disables it.
+    pub ignore_file: Option<String>,
+    /// JSON manifest in `source_directory` naming the file to send; its
+    /// version replaces the modified time for duplicate detection.
+    pub manifest_file: Option<String>,
+    /// Zip every matching file within `check_period` and send that archive,
+    /// named `{name}-{date}.zip`, instead of only the newest file.
+    #[serde(default)]
+    pub bundle: bool,
+    pub max_attempts: Option<u32>,
+    pub retry_delay: Option<String>,
+    /// Re-scan this many times when the selected file disappears before it
+    /// can be opened, e.g. while the backup writer renames it into place.
+    pub open_retries: Option<u32>,
+    /// Delay before each of those re-scans (a duration), default 500ms.
+    pub open_retry_delay: Option<String>,
+    /// Upload size limit in bytes; raise it for boosted servers.
+    pub max_file_size: Option<u64>,
+    /// Gzip the file in memory before uploading.
+    #[serde(default)]
+    pub compress: bool,
+    /// Only compress files of at least this many bytes.
+    pub compress_min_size: Option<u64>,
+    /// "none", "gzip" or "zip"; `compress = true` is the same as "gzip".
+    pub archive: Option<ArchiveFormat>,
+    /// MIME type of the attachment, e.g. "application/octet-stream". Guessed
+    /// from the file extension when unset.
+    pub content_type: Option<String>,
+    /// Multipart field name of the attachment (default "file"), for
+    /// webhook endpoints that expect another name.
+    pub form_field: Option<String>,
+    /// Send the message as a plain form field of this name instead of
+    /// Discord's `payload_json` (e.g. "content").
+    pub text_field: Option<String>,
+    /// Extra HTTP headers sent with every request, e.g. an `Authorization`
+    /// header for a gateway in front of the webhook. Values may use `${VAR}`.
+    pub headers: Option<HashMap<String, String>>,
+    /// Display name for the webhook post.
+    pub username: Option<String>,
+    /// Avatar image URL for the webhook post.
+    pub avatar_url: Option<String>,
+    /// Thread in the webhook's channel to post into.
+    pub thread_id: Option<String>,
+    /// Upload timeout in seconds.
+    pub timeout_secs: Option<u64>,
+    /// HTTP(S) proxy for uploads; overrides `HTTPS_PROXY`/`HTTP_PROXY`.
+    pub proxy_url: Option<String>,
+    /// PEM file with a CA certificate to trust besides the system store, for
+    /// self-hosted webhook servers with an internal CA. Only this CA is
+    /// added; the server's certificate is still fully verified.
+    pub ca_cert_file: Option<String>,
+    /// Skip TLS certificate verification. Anyone between this host and the
+    /// server could then impersonate it and read the uploaded file and the
+    /// webhook token, so use it only for testing, never in production.
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
+    /// Message posted with the file, e.g. "{name}: {filename} ({size})".
+    pub message_template: Option<String>,
+    /// Attachment name shown in Discord, e.g. "prod-backup-{date}.sql.gz".
+    pub upload_filename: Option<String>,
+    /// Append the file's SHA-256 to the message.
+    #[serde(default)]
+    pub include_checksum: bool,
+    /// Post the message as a Discord embed with the file details as fields.
+    #[serde(default)]
+    pub use_embed: bool,
+    /// Embed color as an RGB integer, e.g. 0x2ECC71.
+    pub embed_color: Option<u32>,
+    /// Discord mention to ping, e.g. "<@&role_id>", "<@user_id>" or "@here".
+    pub mention: Option<String>,
+    /// Only mention when the file is larger than this many bytes.
+    pub mention_if_larger_than: Option<u64>,
+    /// Post without triggering push or desktop notifications.
+    #[serde(default)]
+    pub silent: bool,
+    /// Hide link previews in the posted message.
+    #[serde(default)]
+    pub suppress_embeds: bool,
+    /// Post with `wait=true` and log the id of the created message.
+    #[serde(default)]
+    pub confirm_delivery: bool,
+    /// How a message over Discord's 2000 character limit is posted:
+    /// "truncate" (default), "embed" or "error".
+    pub on_long_message: Option<LongMessage>,
+    /// Post a text file under this many bytes inline, in a code block,
+    /// instead of attaching it.
+    pub inline_if_smaller_than: Option<u64>,
+    /// Text encoding of inlined files, e.g. "latin1" or "utf-16le"; a byte
+    /// order mark overrides it. UTF-8 when unset.
+    pub encoding: Option<String>,
+    /// Shell command run after a successful upload, with `SENDER_FILE` and
+    /// `SENDER_BACKUP_NAME` set. A failing hook only logs a warning.
+    pub on_success: Option<String>,
+    /// What to do with the file once it was sent: "keep" (default),
+    /// "delete" or "move:<dir>"; a relative dir is inside `source_directory`.
+    pub after_send: Option<String>,
+    /// Post a text alert to the webhook when no fresh file is found.
+    #[serde(default)]
+    pub notify_on_missing: bool,
+    /// Where to send the file; defaults to Discord.
+    pub target: Option<Target>,
+    /// Slack bot token, required when `target = "slack"`.
+    pub slack_token: Option<String>,
+    /// Slack channel ID, required when `target = "slack"`.
+    pub slack_channel: Option<String>,
+    /// Telegram bot token, required when `target = "telegram"`.
+    pub bot_token: Option<String>,
+    /// Telegram chat ID, required when `target = "telegram"`.
+    pub chat_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    #[default]
+    Discord,
+    Slack,
+    Telegram,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Discord => write!(f, "Discord webhook"),
+            Target::Slack => write!(f, "Slack"),
+            Target::Telegram => write!(f, "Telegram"),
+        }
+    }
+}
+
+/// One or more glob patterns, written in TOML as either a string or a list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Patterns {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Default for Patterns {
+    fn default() -> Self {
+        Patterns::Multiple(Vec::new())
+    }
+}
+
+impl Patterns {
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            Patterns::Single(pattern) => std::slice::from_ref(pattern),
+            Patterns::Multiple(patterns) => patterns,
+        }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [String] {
+        match self {
+            Patterns::Single(pattern) => std::slice::from_mut(pattern),
+            Patterns::Multiple(patterns) => patterns,
+        }
+    }
+}
+
+impl fmt::Display for Patterns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_slice().join(", "))
+    }
+}
+
+/// One or more webhook URLs, written as either a string or a list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum WebhookUrls {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Default for WebhookUrls {
+    fn default() -> Self {
+        WebhookUrls::Single(String::new())
+    }
+}
+
+impl WebhookUrls {
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            WebhookUrls::Single(url) => std::slice::from_ref(url),
+            WebhookUrls::Multiple(urls) => urls,
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [String] {
+        match self {
+            WebhookUrls::Single(url) => std::slice::from_mut(url),
+            WebhookUrls::Multiple(urls) => urls,
+        }
+    }
+
+    /// True when no URL is set.
+    pub fn is_empty(&self) -> bool {
+        self.as_slice().iter().all(String::is_empty)
+    }
+
+    /// The first URL, or "" when there is none.
+    pub fn first(&self) -> &str {
+        self.as_slice().first().map_or("", String::as_str)
+    }
+}
+
+impl From<&str> for WebhookUrls {
+    fn from(url: &str) -> Self {
+        WebhookUrls::Single(url.to_string())
+    }
+}
+
+impl From<String> for WebhookUrls {
+    fn from(url: String) -> Self {
+        WebhookUrls::Single(url)
+    }
+}
+
+impl PartialEq<&str> for WebhookUrls {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_slice() == [*other]
+    }
+}
+
+/// One or more directories to search, written as either a string or a list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SourceDirectories {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Default for SourceDirectories {
+    fn default() -> Self {
+        SourceDirectories::Single(String::new())
+    }
+}
+
+impl SourceDirectories {
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            SourceDirectories::Single(dir) => std::slice::from_ref(dir),
+            SourceDirectories::Multiple(dirs) => dirs,
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [String] {
+        match self {
+            SourceDirectories::Single(dir) => std::slice::from_mut(dir),
+            SourceDirectories::Multiple(dirs) => dirs,
+        }
+    }
+
+    /// The first directory, or "" when there is none.
+    pub fn first(&self) -> &str {
+        self.as_slice().first().map_or("", String::as_str)
+    }
+
+    /// The directory `path` was found in, falling back to the first one.
+    pub fn containing(&self, path: &Path) -> &str {
+        self.as_slice()
+            .iter()
+            .find(|dir| !dir.is_empty() && path.starts_with(dir))
+            .map_or_else(|| self.first(), String::as_str)
+    }
+}
+
+impl fmt::Display for SourceDirectories {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_slice().join(", "))
+    }
+}
+
+impl From<&str> for SourceDirectories {
+    fn from(dir: &str) -> Self {
+        SourceDirectories::Single(dir.to_string())
+    }
+}
+
+impl From<String> for SourceDirectories {
+    fn from(dir: String) -> Self {
+        SourceDirectories::Single(dir)
+    }
+}
+
+impl PartialEq<&str> for SourceDirectories {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_slice() == [*other]
+    }
+}
+
+impl Config {
+    /// Loads a config file, choosing the format from its extension: `.toml`
+    /// (also used when there is no extension), `.yaml`/`.yml` or `.json`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        let parse_error = |message: String| SenderError::ConfigParse {
+            path: path.to_path_buf(),
+            message,
+        };
+        let mut config: Config = match extension.as_deref() {
+            None | Some("toml") => {
+                toml::from_str(&content).map_err(|e| parse_error(e.to_string()))?
+            }
+            Some("yaml" | "yml") => {
+                serde_yaml::from_str(&content).map_err(|e| parse_error(e.to_string()))?
+            }
+            Some("json") => {
+                serde_json::from_str(&content).map_err(|e| parse_error(e.to_string()))?
+            }
+            Some(other) => bail_config!(
+                "Unsupported config file extension '.{other}'; supported formats are .toml, .yaml, .yml and .json"
+            ),
+        };
+        config.expand_env_vars()?;
+        if let Some(base) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            config.resolve_relative_paths(base);
+        }
+        config.read_webhook_url_files()?;
+
+        for backup in &config.backups {
+            if let Some(template) = &backup.message_template {
+                validate_template(template, &MESSAGE_PLACEHOLDERS).map_err(|e| {
+                    SenderError::ConfigInvalid(format!(
+                        "backup '{}': message_template: {e}",
+                        backup.name
+                    ))
+                })?;
+            }
+            if let Some(template) = &backup.upload_filename {
+                validate_template(template, &UPLOAD_FILENAME_PLACEHOLDERS).map_err(|e| {
+                    SenderError::ConfigInvalid(format!(
+                        "backup '{}': upload_filename: {e}",
+                        backup.name
+                    ))
+                })?;
+            }
+            if let Some(mention) = &backup.mention {
+                AllowedMentions::for_mention(mention).map_err(|e| {
+                    SenderError::ConfigInvalid(format!("backup '{}': mention: {e}", backup.name))
+                })?;
+            }
+            backup.with_placeholders(Local::now()).map_err(|e| {
+                SenderError::ConfigInvalid(format!("backup '{}': {e:#}", backup.name))
+            })?;
+            if backup.target.unwrap_or_default() == Target::Discord {
+                for url in backup.webhook_url.as_slice() {
+                    backup.validate_discord_url(url).map_err(|e| {
+                        SenderError::ConfigInvalid(format!("backup '{}': {e}", backup.name))
+                    })?;
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Loads every `*.toml` file in `dir`, sorted by file name. Each file is
+    /// returned with its own result so one broken file doesn't hide the rest.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<(PathBuf, Result<Self>)>> {
+        let dir = dir.as_ref();
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {dir:?}"))? {
+            let path = entry
+                .with_context(|| format!("Failed to read {dir:?}"))?
+                .path();
+            if path.is_file() && path.extension().is_some_and(|e| e == "toml") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        Ok(paths
+            .into_iter()
+            .map(|path| {
+                let config = Self::from_file(&path);
+                (path, config)
+            })
+            .collect())
+    }
+
+    /// Writes [`CONFIG_TEMPLATE`] to `path`. An existing file is only
+    /// replaced when `overwrite` is true.
+    pub fn write_template<P: AsRef<Path>>(path: P, overwrite: bool) -> Result<()> {
+        let path = path.as_ref();
+        let mut options = fs::OpenOptions::new();
+        options.write(true);
+        if overwrite {
+            options.create(true).truncate(true);
+        } else {
+            options.create_new(true);
+        }
+
+        let mut file = options.open(path).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::AlreadyExists {
+                SenderError::Other(format!(
+                    "{path:?} already exists; use --force to overwrite it"
+                ))
+            } else {
+                SenderError::Io(e).context(format!("Failed to create {path:?}"))
+            }
+        })?;
+        file.write_all(CONFIG_TEMPLATE.as_bytes())
+            .with_context(|| format!("Failed to write {path:?}"))
+    }
+
+    /// Writes the config to `path` as pretty-printed TOML, replacing any
+    /// existing file. Values are written as loaded, so `${VAR}` references
+    /// appear expanded; a backup loaded from `webhook_url_file` keeps only
+    /// the file reference, so the URL read from it is not written out.
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut config = self.clone();
+        for backup in &mut config.backups {
+            if backup.webhook_url_file.is_some() {
+                backup.webhook_url = WebhookUrls::default();
+            }
+        }
+        let content = toml::to_string_pretty(&config).context("Failed to serialize config")?;
+        fs::write(path, content).with_context(|| format!("Failed to write {path:?}"))
+    }
+
+    /// Makes relative `source_directory`, `ca_cert_file` and
+    /// `webhook_url_file` values relative to `base`, the directory of the
+    /// config file, rather than the working directory.
+    fn resolve_relative_paths(&mut self, base: &Path) {
+        for backup in &mut self.backups {
+            let ca_cert_file = backup.ca_cert_file.as_mut();
+            let webhook_url_file = backup.webhook_url_file.as_mut();
+            for path in backup
+                .source_directory
+                .as_mut_slice()
+                .iter_mut()
+                .chain(ca_cert_file)
+                .chain(webhook_url_file)
+            {
+                if !path.is_empty() && Path::new(path).is_relative() {
+                    *path = base.join(&*path).to_string_lossy().into_owned();
+                }
+            }
+        }
+    }
+
+    /// Resolves `${VAR}` references in `summary_webhook`, `webhook_url`,
+    /// `source_directory` and `headers` values.
+    fn expand_env_vars(&mut self) -> Result<()> {
+        if let Some(url) = &mut self.summary_webhook {
+            *url = expand_env(url)
+                .map_err(|e| SenderError::ConfigInvalid(format!("summary_webhook: {e}")))?;
+        }
+        for backup in &mut self.backups {
+            let name = backup.name.clone();
+            let urls = backup.webhook_url.as_mut_slice().iter_mut();
+            let headers = backup.headers.iter_mut().flat_map(|h| h.values_mut());
+            for value in urls
+                .chain(backup.source_directory.as_mut_slice())
+                .chain(headers)
+            {
+                *value = expand_env(value)
+                    .map_err(|e| SenderError::ConfigInvalid(format!("backup '{name}': {e}")))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills `webhook_url` from `webhook_url_file`, requiring exactly one of
+    /// the two for Discord and at most one for other targets.
+    fn read_webhook_url_files(&mut self) -> Result<()> {
+        for backup in &mut self.backups {
+            let name = &backup.name;
+            match &backup.webhook_url_file {
+                Some(_) if !backup.webhook_url.is_empty() => {
+                    bail_config!(
+                        "backup '{name}': set either webhook_url or webhook_url_file, not both"
+                    )
+                }
+                Some(path) => {
+                    let url = fs::read_to_string(path).with_context(|| {
+                        format!("backup '{name}': Failed to read webhook_url_file {path:?}")
+                    })?;
+                    backup.webhook_url = WebhookUrls::Single(url.trim().to_string());
+                }
+                None if backup.webhook_url.is_empty()
+                    && backup.target.unwrap_or_default() == Target::Discord =>
+                {
+                    bail_config!("backup '{name}': webhook_url or webhook_url_file is required")
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks every backup without touching the network, returning a
+    /// description of each problem found. Local paths are looked at: source
+    /// directories must exist and `ca_cert_file` must hold a PEM certificate.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if let Some(url) = &self.summary_webhook {
+            if let Err(problem) = validate_webhook_url(url) {
+                problems.push(format!("summary_webhook: {problem}"));
+            }
+        }
+        for (i, backup) in self.backups.iter().enumerate() {
+            if self.backups[..i].iter().any(|b| b.name == backup.name) {
+                problems.push(format!("backup '{}': duplicate name", backup.name));
+            }
+            problems.extend(
+                backup
+                    .validate()
+                    .into_iter()
+                    .map(|problem| format!("backup '{}': {problem}", backup.name)),
+            );
+        }
+
+        problems
+    }
+}
+
+impl BackupConfig {
+    /// A copy with `{name}` and `{date}` rendered in `source_directory` and
+    /// `file_pattern`, for a run on `today`.
+    pub fn with_placeholders(&self, today: DateTime<Local>) -> Result<Self> {
+        let mut backup = self.clone();
+        for directory in backup.source_directory.as_mut_slice() {
+            *directory = render_path(directory, &self.name, today).context("source_directory")?;
+        }
+        for pattern in backup.file_pattern.as_mut_slice() {
+            *pattern = render_path(pattern, &self.name, today).context("file_pattern")?;
+        }
+        Ok(backup)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    /// Discord message flags for `silent` and `suppress_embeds`.
+    pub fn message_flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.silent {
+            flags |= FLAG_SUPPRESS_NOTIFICATIONS;
+        }
+        if self.suppress_embeds {
+            flags |= FLAG_SUPPRESS_EMBEDS;
+        }
+        flags
+    }
+
+    /// The `archive` setting, falling back to gzip when `compress` is set.
+    pub fn archive_format(&self) -> ArchiveFormat {
+        match self.archive {
+            Some(format) => format,
+            None if self.compress => ArchiveFormat::Gzip,
+            None => ArchiveFormat::None,
+        }
+    }
+
+    pub fn selection(&self) -> Result<SelectionStrategy> {
+        match self.select_by.as_deref() {
+            None | Some("mtime") => Ok(SelectionStrategy::Mtime),
+            Some("largest") => Ok(SelectionStrategy::LargestSize),
+            Some("smallest") => Ok(SelectionStrategy::SmallestSize),
+            Some(other) => {
+                bail_config!("'{other}' is not one of \"mtime\", \"largest\" or \"smallest\"")
+            }
+        }
+    }
+
+    pub fn encoding(&self) -> Result<&'static Encoding> {
+        match &self.encoding {
+            None => Ok(UTF_8),
+            Some(label) => Encoding::for_label(label.trim().as_bytes()).ok_or_else(|| {
+                SenderError::ConfigInvalid(format!("'{label}' is not a known encoding"))
+            }),
+        }
+    }
+
+    pub fn dedup(&self) -> Result<Dedup> {
+        match self.dedup.as_deref() {
+            None | Some("mtime") => Ok(Dedup::Mtime),
+            Some("path") => Ok(Dedup::Path),
+            Some("hash") => Ok(Dedup::Hash),
+            Some("none") => Ok(Dedup::Off),
+            Some(other) => {
+                bail_config!("'{other}' is not one of \"path\", \"mtime\", \"hash\" or \"none\"")
+            }
+        }
+    }
+
+    /// `headers` as a header map. Content-Type is refused: the sender sets it
+    /// to the multipart boundary or JSON, and a second one would break the
+    /// request. Values are marked sensitive so they are never logged.
+    pub fn header_map(&self) -> Result<HeaderMap> {
+        let mut map = HeaderMap::new();
+        for (name, value) in self.headers.iter().flatten() {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                SenderError::ConfigInvalid(format!("'{name}' is not a valid header name"))
+            })?;
+            if name == CONTENT_TYPE {
+                bail_config!("Content-Type is set by latest-sender and can't be overridden");
+            }
+            let mut value = HeaderValue::from_str(value).map_err(|_| {
+                SenderError::ConfigInvalid(format!(
+                    "the value of '{name}' is not a valid header value"
+                ))
+            })?;
+            value.set_sensitive(true);
+            map.insert(name, value);
+        }
+        Ok(map)
+    }
+
+    /// The parsed `after_send` action for `file`, with a relative move
+    /// directory resolved against the source directory `file` was found in.
+    pub fn after_send(&self, file: &Path) -> Result<AfterSend> {
+        let action = match &self.after_send {
+            None => AfterSend::Keep,
+            Some(value) => AfterSend::parse(value)?,
+        };
+        Ok(match action {
+            AfterSend::Move(dir) if dir.is_relative() => {
+                AfterSend::Move(Path::new(self.source_directory.containing(file)).join(dir))
+            }
+            action => action,
+        })
+    }
+
+    pub fn parse_check_period(&self) -> Result<Option<Duration>> {
+        match &self.check_period {
+            None => Ok(None),
+            Some(period_str) => {
+                let period = parse_duration_string(period_str)?;
+                Ok(Some(period))
+            }
+        }
+    }
+
+    pub fn parse_once_per(&self) -> Result<Option<Duration>> {
+        self.once_per
+            .as_deref()
+            .map(parse_duration_string)
+            .transpose()
+    }
+
+    /// The mention to add for a file of `size` bytes, honoring
+    /// `mention_if_larger_than`.
+    pub fn mention_for(&self, size: u64) -> Option<&str> {
+        match self.mention_if_larger_than {
+            Some(threshold) if size <= threshold => None,
+            _ => self.mention.as_deref(),
+        }
+    }
+
+    /// Parses `newer_than` as an RFC 3339 timestamp.
+    pub fn parse_newer_than(&self) -> Result<Option<DateTime<Local>>> {
+        self.newer_than
+            .as_deref()
+            .map(|value| {
+                DateTime::parse_from_rfc3339(value)
+                    .map(|time| time.with_timezone(&Local))
+                    .map_err(|e| {
+                        SenderError::ConfigInvalid(format!(
+                            "Invalid RFC 3339 timestamp '{value}': {e}"
+                        ))
+                    })
+            })
+            .transpose()
+    }
+
+    /// The configured cutoff(s) for messages, e.g. "1d" or "1d / 2024-01-01T00:00:00Z".
+    pub fn cutoff_description(&self) -> Option<String> {
+        match (&self.check_period, &self.newer_than) {
+            (Some(period), Some(time)) => Some(format!("{period} / {time}")),
+            (Some(cutoff), None) | (None, Some(cutoff)) => Some(cutoff.clone()),
+            (None, None) => None,
+        }
+    }
+
+    pub fn search_options(&self) -> Result<SearchOptions> {
+        let check_period = self.parse_check_period().with_context(|| {
+            format!(
+                "check_period '{}'",
+                self.check_period.as_deref().unwrap_or_default()
+            )
+        })?;
+
+        let newer_than = self.parse_newer_than().context("newer_than")?;
+        let selection = self.selection().context("select_by")?;
+
+        Ok(SearchOptions {
+            check_period,
+            newer_than,
+            exclude_patterns: self.exclude_patterns.clone(),
+            recursive: self.recursive,
+            follow_symlinks: self.follow_symlinks.unwrap_or(true),
+            min_size_bytes: self.min_size_bytes,
+            skip_unreadable: self.skip_unreadable,
+            case_insensitive: self.case_insensitive,
+            ignore_file: match self.ignore_file.as_deref() {
+                None => Some(DEFAULT_IGNORE_FILE.to_string()),
+                Some("") => None,
+                Some(name) => Some(name.to_string()),
+            },
+            selection,
+            scan_cache: None,
+        })
+    }
+
+    pub fn retry_policy(&self) -> Result<RetryPolicy> {
+        let mut policy = RetryPolicy::default();
+        if let Some(max_attempts) = self.max_attempts {
+            policy.max_attempts = max_attempts;
+        }
+        if let Some(delay_str) = &self.retry_delay {
+            policy.base_delay = humantime::parse_duration(delay_str).map_err(|e| {
+                SenderError::ConfigInvalid(format!("Invalid duration format '{delay_str}': {e}"))
+            })?;
+        }
+        Ok(policy)
+    }
+
+    /// Checks a webhook URL of a Discord-target backup. With `form_field` or
+    /// `text_field` set the endpoint needn't be Discord, so any http(s) URL
+    /// is accepted.
+    fn validate_discord_url(&self, url: &str) -> std::result::Result<(), String> {
+        if self.form_field.is_some() || self.text_field.is_some() {
+            validate_http_url(url)
+        } else {
+            validate_webhook_url(url)
+        }
+    }
+
+    pub fn open_retry_delay(&self) -> Result<std::time::Duration> {
+        match &self.open_retry_delay {
+            None => Ok(DEFAULT_OPEN_RETRY_DELAY),
+            Some(delay_str) => humantime::parse_duration(delay_str).map_err(|e| {
+                SenderError::ConfigInvalid(format!("Invalid duration format '{delay_str}': {e}"))
+            }),
+        }
+    }
+
+    /// Checks the fields of this backup, returning a description of each problem found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.name.trim().is_empty() {
+            problems.push("name is empty".to_string());
+        }
+
+        let target = self.target.unwrap_or_default();
+        if target == Target::Discord {
+            if self.webhook_url.as_slice().is_empty() {
+                problems.push("webhook_url list is empty".to_string());
+            }
+            for url in self.webhook_url.as_slice() {
+                if let Err(problem) = self.validate_discord_url(url) {
+                    problems.push(problem);
+                }
+            }
+        } else if self.webhook_url.as_slice().len() > 1 {
+            problems.push("multiple webhook_url values are only supported for Discord".to_string());
+        } else if !self.webhook_url.is_empty() {
+            if let Err(problem) = validate_http_url(self.webhook_url.first()) {
+                problems.push(problem);
+            }
+        }
+        if target == Target::Slack {
+            if self.slack_token.is_none() {
+                problems.push("slack_token is required for target \"slack\"".to_string());
+            }
+            if self.slack_channel.is_none() {
+                problems.push("slack_channel is required for target \"slack\"".to_string());
+            }
+        }
+        if target == Target::Telegram {
+            if self.bot_token.is_none() {
+                problems.push("bot_token is required for target \"telegram\"".to_string());
+            }
+            if self.chat_id.is_none() {
+                problems.push("chat_id is required for target \"telegram\"".to_string());
+            }
+        }
+        if target != Target::Discord {
+            if self.use_embed {
+                problems.push("use_embed is only supported for Discord".to_string());
+            }
+            if self.mention.is_some() {
+                problems.push("mention is only supported for Discord".to_string());
+            }
+            if self.notify_on_missing {
+                problems.push("notify_on_missing is only supported for Discord".to_string());
+            }
+            if self.content_type.is_some() {
+                problems.push("content_type is only supported for Discord".to_string());
+            }
+            if self.ca_cert_file.is_some() || self.danger_accept_invalid_certs {
+                problems.push(
+                    "ca_cert_file and danger_accept_invalid_certs are only supported for Discord"
+                        .to_string(),
+                );
+            }
+            if self.headers.is_some() {
+                problems.push("headers are only supported for Discord".to_string());
+            }
+            if self.form_field.is_some() || self.text_field.is_some() {
+                problems
+                    .push("form_field and text_field are only supported for Discord".to_string());
+            }
+            if self.confirm_delivery {
+                problems.push("confirm_delivery is only supported for Discord".to_string());
+            }
+            if self.on_long_message.is_some() {
+                problems.push("on_long_message is only supported for Discord".to_string());
+            }
+            if self.inline_if_smaller_than.is_some() {
+                problems.push("inline_if_smaller_than is only supported for Discord".to_string());
+            }
+            if self.silent || self.suppress_embeds {
+                problems
+                    .push("silent and suppress_embeds are only supported for Discord".to_string());
+            }
+        }
+
+        if let Some(mention) = &self.mention {
+            if let Err(e) = AllowedMentions::for_mention(mention) {
+                problems.push(format!("mention: {e}"));
+            }
+        }
+        for (key, field) in [
+            ("form_field", &self.form_field),
+            ("text_field", &self.text_field),
+        ] {
+            if field.as_deref().is_some_and(|name| name.trim().is_empty()) {
+                problems.push(format!("{key} must not be empty"));
+            }
+        }
+
+        if let Err(e) = self.header_map() {
+            problems.push(format!("headers: {e}"));
+        }
+
+        if let Some(content_type) = &self.content_type {
+            if content_type.parse::<mime_guess::mime::Mime>().is_err() {
+                problems.push(format!(
+                    "content_type '{content_type}' is not a valid MIME type"
+                ));
+            }
+        }
+        if let Some(proxy_url) = &self.proxy_url {
+            if let Err(e) = reqwest::Proxy::all(proxy_url) {
+                problems.push(format!("proxy_url is not a valid proxy URL: {e}"));
+            }
+        }
+        if let Some(ca_cert_file) = &self.ca_cert_file {
+            match fs::read(ca_cert_file) {
+                Ok(pem) => {
+                    if let Err(e) = reqwest::Certificate::from_pem(&pem) {
+                        problems.push(format!(
+                            "ca_cert_file {ca_cert_file} is not a PEM certificate: {e}"
+                        ));
+                    }
+                }
+                Err(e) => problems.push(format!("ca_cert_file {ca_cert_file}: {e}")),
+            }
+        }
+        if self.compress && self.archive.is_some() {
+            problems.push("set either compress or archive, not both".to_string());
+        }
+        if let Err(e) = self.parse_check_period() {
+            problems.push(format!("check_period: {e}"));
+        }
+        if let Err(e) = self.parse_newer_than() {
+            problems.push(format!("newer_than: {e}"));
+        }
+        if let Err(e) = self.selection() {
+            problems.push(format!("select_by: {e}"));
+        }
+        if let Err(e) = self.encoding() {
+            problems.push(format!("encoding: {e}"));
+        }
+        if let Err(e) = self.dedup() {
+            problems.push(format!("dedup: {e}"));
+        }
+        if let Some(Err(e)) = self.after_send.as_deref().map(AfterSend::parse) {
+            problems.push(format!("after_send: {e}"));
+        }
+        if self.bundle {
+            if self.manifest_file.is_some() {
+                problems.push("set either bundle or manifest_file, not both".to_string());
+            }
+            if self.inline_if_smaller_than.is_some() {
+                problems.push("inline_if_smaller_than can't be used with bundle".to_string());
+            }
+            if let Some(Ok(AfterSend::Delete | AfterSend::Move(_))) =
+                self.after_send.as_deref().map(AfterSend::parse)
+            {
+                problems.push("after_send can't be used with bundle".to_string());
+            }
+            if matches!(self.dedup(), Ok(Dedup::Hash)) {
+                problems.push("dedup = \"hash\" can't be used with bundle".to_string());
+            }
+        }
+        if let Err(e) = self.parse_once_per() {
+            problems.push(format!("once_per: {e}"));
+        }
+        if let Err(e) = self.retry_policy() {
+            problems.push(format!("retry_delay: {e}"));
+        }
+        if let Err(e) = self.open_retry_delay() {
+            problems.push(format!("open_retry_delay: {e}"));
+        }
+        if let Some(template) = &self.message_template {
+            if let Err(e) = validate_template(template, &MESSAGE_PLACEHOLDERS) {
+                problems.push(format!("message_template: {e}"));
+            }
+        }
+        if let Some(template) = &self.upload_filename {
+            if let Err(e) = validate_template(template, &UPLOAD_FILENAME_PLACEHOLDERS) {
+                problems.push(format!("upload_filename: {e}"));
+            }
+            if template.contains(['/', '\\']) {
+                problems.push("upload_filename must not contain path separators".to_string());
+            }
+        }
+
+        if self.source_directory.as_slice().is_empty() {
+            problems.push("source_directory list is empty".to_string());
+        }
+        // Checked as today's run would see them
+        let resolved = match self.with_placeholders(Local::now()) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                problems.push(format!("{e:#}"));
+                self.clone()
+            }
+        };
+        for dir in resolved.source_directory.as_slice() {
+            let source = Path::new(dir);
+            if !source.exists() {
+                problems.push(format!("source_directory {dir:?} does not exist"));
+            } else if !source.is_dir() {
+                problems.push(format!("source_directory {dir:?} is not a directory"));
+            }
+        }
+
+        if self.file_pattern.as_slice().is_empty() {
+            problems.push("file_pattern is empty".to_string());
+        }
+        for pattern in resolved.file_pattern.as_slice() {
+            if let Err(e) = compile_pattern("file_pattern", pattern) {
+                problems.push(e.to_string());
+            }
+        }
+        for pattern in &self.exclude_patterns {
+            if let Err(e) = compile_pattern("exclude_patterns", pattern) {
+                problems.push(e.to_string());
+            }
+        }
+
+        problems
+    }
+
+    pub fn send_options(&self) -> Result<SendOptions> {
+        Ok(SendOptions {
+            retry: self.retry_policy()?,
+            max_file_size: self.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE),
+            compress_min_size: (self.archive_format() != ArchiveFormat::None)
+                .then(|| self.compress_min_size.unwrap_or(0)),
+            archive: self.archive_format(),
+            username: self.username.clone(),
+            avatar_url: self.avatar_url.clone(),
+            thread_id: self.thread_id.clone(),
+            timeout: self
+                .timeout_secs
+                .map(std::time::Duration::from_secs)
+                .unwrap_or(DEFAULT_TIMEOUT),
+            proxy_url: self.proxy_url.clone(),
+            ca_cert_file: self.ca_cert_file.as_ref().map(PathBuf::from),
+            danger_accept_invalid_certs: self.danger_accept_invalid_certs,
+            embed: self.use_embed.then(|| Embed {
+                title: self.name.clone(),
+                description: None,
+                color: self.embed_color.unwrap_or(DEFAULT_EMBED_COLOR),
+                fields: Vec::new(),
+            }),
+            // Depends on the file size, see `mention_for`
+            mention: None,
+            // Rendered from `upload_filename` once the file is known
+            upload_filename: None,
+            flags: self.message_flags(),
+            content_type: self.content_type.clone(),
+            form_field: self.form_field.clone(),
+            text_field: self.text_field.clone(),
+            headers: self.header_map().context("Invalid headers")?,
+            confirm_delivery: self.confirm_delivery,
+            // Set per run with `--progress`
+            progress: false,
+            on_long_message: self.on_long_message.unwrap_or_default(),
+        })
+    }
+}
+
+/// Checks that `url` looks like a Discord webhook URL:
+/// `https://<host>/api[/v<N>]/webhooks/<id>/<token>`. Catches invite and
+/// channel links pasted by mistake before any request is made.
+fn validate_webhook_url(url: &str) -> std::result::Result<(), String> {
+    let parsed =
+        reqwest::Url::parse(url).map_err(|e| format!("webhook_url is not a valid URL: {e}"))?;
+    if parsed.scheme() != "https" {
+        return Err(format!(
+            "webhook_url must use https, not '{}'",
+            parsed.scheme()
+        ));
+    }
+    if parsed.host_str() == Some("discord.gg") || parsed.path().starts_with("/invite/") {
+        return Err("webhook_url is an invite link, not a webhook URL".to_string());
+    }
+    if parsed.path().starts_with("/channels/") {
+        return Err("webhook_url is a channel link, not a webhook URL".to_string());
+    }
+
+    let mut segments: Vec<&str> = parsed.path().trim_end_matches('/').split('/').collect();
+    // Drop the leading empty segment and an optional API version
+    segments.remove(0);
+    if segments.len() == 5 && segments[1].starts_with('v') {
+        segments.remove(1);
+    }
+    match segments.as_slice() {
+        ["api", "webhooks", id, token] if !token.is_empty() => {
+            if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(format!("webhook_url ID '{id}' must be numeric"));
+            }
+            Ok(())
+        }
+        _ => Err(format!(
+            "webhook_url path '{}' does not match /api/webhooks/<id>/<token>",
+            parsed.path()
+        )),
+    }
+}
+
+/// Checks that `url` is a valid http or https URL.
+fn validate_http_url(url: &str) -> std::result::Result<(), String> {
+    match reqwest::Url::parse(url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(()),
+        Ok(url) => Err(format!(
+            "webhook_url must use http or https, not '{}'",
+            url.scheme()
+        )),
+        Err(e) => Err(format!("webhook_url is not a valid URL: {e}")),
+    }
+}
+
+/// Replaces every `${VAR}` in `value` with the value of the environment variable.
+fn expand_env(value: &str) -> Result<String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find('}').ok_or_else(|| {
+            SenderError::ConfigInvalid(format!("unterminated '${{' in '{value}'"))
+        })?;
+        let var = &after[..end];
+        let resolved = std::env::var(var).map_err(|_| {
+            SenderError::ConfigInvalid(format!("environment variable '{var}' is not set"))
+        })?;
+        expanded.push_str(&resolved);
+        rest = &after[end + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// Parses a humantime duration such as `"1d"` or `"2h 30m"`.
+pub fn parse_duration_string(duration_str: &str) -> Result<Duration> {
+    let std_duration = humantime::parse_duration(duration_str).map_err(|e| {
+        SenderError::ConfigInvalid(format!("Invalid duration format '{duration_str}': {e}"))
+    })?;
+
+    // Convert std::time::Duration to chrono::Duration
+    let chrono_duration = Duration::from_std(std_duration)
+        .map_err(|e| SenderError::ConfigInvalid(format!("Duration too large: {e}")))?;
+
+    Ok(chrono_duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::io::Write;
+    use tempfile::{NamedTempFile, TempDir};
+
+    #[test]
+    fn test_config_from_file() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(
+            file,
+            r#"
+[[backups]]
+name = "test_backup"
+source_directory = "/path/to/source"
+file_pattern = "*.txt"
+webhook_url = "https://discord.com/api/webhooks/1/test"
+
+[[backups]]
+name = "another_backup"
+source_directory = "/another/path"
+file_pattern = "*.log"
+webhook_url = "https://discord.com/api/webhooks/2/another"
+"#
+        )?;
+
+        let config = Config::from_file(file.path())?;
+        assert_eq!(config.backups.len(), 2);
+        assert_eq!(config.backups[0].name, "test_backup");
+        assert_eq!(config.backups[0].source_directory, "/path/to/source");
+        assert_eq!(
+            config.backups[0].file_pattern,
+            Patterns::Single("*.txt".to_string())
+        );
+        assert_eq!(
+            config.backups[0].webhook_url,
+            "https://discord.com/api/webhooks/1/test"
+        );
+
+        assert_eq!(config.backups[1].name, "another_backup");
+        assert_eq!(config.backups[1].source_directory, "/another/path");
+        assert_eq!(
+            config.backups[1].file_pattern,
+            Patterns::Single("*.log".to_string())
+        );
+        assert_eq!(
+            config.backups[1].webhook_url,
+            "https://discord.com/api/webhooks/2/another"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_file_pattern_string_or_list() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(
+            file,
+            r#"
+[[backups]]
+name = "single"
+source_directory = "/path/to/source"
+file_pattern = "*.sql.gz"
+webhook_url = "https://discord.com/api/webhooks/1/test"
+
+[[backups]]
+name = "multiple"
+source_directory = "/path/to/source"
+file_pattern = ["*.sql.gz", "*.dump"]
+webhook_url = "https://discord.com/api/webhooks/1/test"
+"#
+        )?;
+
+        let config = Config::from_file(file.path())?;
+        assert_eq!(config.backups[0].file_pattern.as_slice(), ["*.sql.gz"]);
+        assert_eq!(
+            config.backups[1].file_pattern.as_slice(),
+            ["*.sql.gz", "*.dump"]
+        );
+        assert_eq!(
+            config.backups[1].file_pattern.to_string(),
+            "*.sql.gz, *.dump"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_duration_string() -> Result<()> {
+        // Test valid formats
+        assert_eq!(parse_duration_string("1d")?, Duration::days(1));
+        assert_eq!(parse_duration_string("24h")?, Duration::hours(24));
+        assert_eq!(parse_duration_string("30m")?, Duration::minutes(30));
+        assert_eq!(parse_duration_string("1w")?, Duration::weeks(1));
+        assert_eq!(
+            parse_duration_string("2d 3h")?,
+            Duration::days(2) + Duration::hours(3)
+        );
+
+        // Test invalid formats
+        assert!(parse_duration_string("invalid").is_err());
+        assert!(parse_duration_string("").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_config_parse_check_period() -> Result<()> {
+        let config = BackupConfig {
+            name: "test".to_string(),
+            source_directory: "/tmp".into(),
+            file_pattern: Patterns::Single("*.txt".to_string()),
+            webhook_url: "http://example.com".into(),
+            check_period: Some("24h".to_string()),
+            ..Default::default()
+        };
+
+        let period = config.parse_check_period()?;
+        assert_eq!(period, Some(Duration::hours(24)));
+
+        let config_none = BackupConfig {
+            name: "test".to_string(),
+            source_directory: "/tmp".into(),
+            file_pattern: Patterns::Single("*.txt".to_string()),
+            webhook_url: "http://example.com".into(),
+            check_period: None,
+            ..Default::default()
+        };
+
+        let period_none = config_none.parse_check_period()?;
+        assert_eq!(period_none, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_config_retry_policy() -> Result<()> {
+        let config = BackupConfig {
+            max_attempts: Some(5),
+            retry_delay: Some("500ms".to_string()),
+            ..Default::default()
+        };
+
+        let policy = config.retry_policy()?;
+        assert_eq!(policy.max_attempts, 5);
+        assert_eq!(policy.base_delay, std::time::Duration::from_millis(500));
+
+        assert_eq!(
+            BackupConfig::default().retry_policy()?,
+            RetryPolicy::default()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_config_validate() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+
+        let valid = BackupConfig {
+            name: "db".to_string(),
+            source_directory: temp_dir.path().to_str().unwrap().into(),
+            file_pattern: Patterns::Single("*.sql".to_string()),
+            webhook_url: "https://discord.com/api/webhooks/1/token".into(),
+            check_period: Some("1d".to_string()),
+            ..Default::default()
+        };
+        assert!(valid.validate().is_empty());
+
+        let invalid = BackupConfig {
+            name: "db".to_string(),
+            source_directory: temp_dir.path().join("missing").to_str().unwrap().into(),
+            file_pattern: Patterns::Single("*.[sql".to_string()),
+            webhook_url: "discord.com/api/webhooks/1/token".into(),
+            check_period: Some("1 fortnight".to_string()),
+            ..Default::default()
+        };
+        let problems = invalid.validate();
+        assert_eq!(problems.len(), 4, "{problems:?}");
+        assert!(problems[0].starts_with("webhook_url is not a valid URL"));
+        assert!(problems[1].starts_with("check_period"));
+        assert!(problems[2].contains("does not exist"));
+        assert!(problems[3].starts_with("file_pattern '*.[sql' is invalid"));
+
+        let config = Config {
+            summary_webhook: Some("https://discord.com/channels/1/2".to_string()),
+            backups: vec![valid.clone(), valid],
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("summary_webhook: "));
+        assert_eq!(problems[1], "backup 'db': duplicate name");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_map() {
+        let backup = BackupConfig {
+            headers: Some(HashMap::from([(
+                "Authorization".to_string(),
+                "Bearer token".to_string(),
+            )])),
+            ..Default::default()
+        };
+        let map = backup.header_map().unwrap();
+        assert_eq!(map["authorization"], "Bearer token");
+        assert!(map["authorization"].is_sensitive());
+        assert!(BackupConfig::default().header_map().unwrap().is_empty());
+
+        for (name, value, error) in [
+            (
+                "content-type",
+                "text/plain",
+                "Content-Type is set by latest-sender and can't be overridden",
+            ),
+            ("bad header", "x", "'bad header' is not a valid header name"),
+            (
+                "X-Token",
+                "a\nb",
+                "the value of 'x-token' is not a valid header value",
+            ),
+        ] {
+            let backup = BackupConfig {
+                headers: Some(HashMap::from([(name.to_string(), value.to_string())])),
+                ..Default::default()
+            };
+            assert_eq!(backup.header_map().unwrap_err().to_string(), error);
+        }
+    }
+
+    #[test]
+    fn test_with_placeholders() -> Result<()> {
+        let backup = BackupConfig {
+            name: "prod".to_string(),
+            source_directory: "/srv/{name}".into(),
+            file_pattern: Patterns::Multiple(vec![
+                "{name}-{date}.sql".to_string(),
+                "*.{sql,gz}".to_string(),
+            ]),
+            ..Default::default()
+        };
+        let today = Local.with_ymd_and_hms(2024, 3, 9, 12, 0, 0).unwrap();
+
+        let resolved = backup.with_placeholders(today)?;
+        assert_eq!(resolved.source_directory, "/srv/prod");
+        assert_eq!(
+            resolved.file_pattern.as_slice(),
+            ["prod-2024-03-09.sql", "*.{sql,gz}"]
+        );
+        assert_eq!(resolved.name, "prod");
+
+        let mut file = NamedTempFile::with_suffix(".toml")?;
+        writeln!(
+            file,
+            r#"
+[[backups]]
+name = "prod"
+source_directory = "/srv/{{host}}"
+file_pattern = "*.sql"
+webhook_url = "https://discord.com/api/webhooks/1/token"
+"#
+        )?;
+        let error = Config::from_file(file.path()).unwrap_err();
+        assert_eq!(
+            format!("{error:#}"),
+            "backup 'prod': source_directory: unknown placeholder '{host}' in template '/srv/{host}'; use {name} or {date}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_bundle() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let valid = BackupConfig {
+            name: "db".to_string(),
+            source_directory: temp_dir.path().to_str().unwrap().into(),
+            file_pattern: Patterns::Single("*.sql".to_string()),
+            webhook_url: "https://discord.com/api/webhooks/1/token".into(),
+            bundle: true,
+            ..Default::default()
+        };
+
+        let backup = BackupConfig {
+            manifest_file: Some("latest.json".to_string()),
+            after_send: Some("delete".to_string()),
+            dedup: Some("hash".to_string()),
+            ..valid.clone()
+        };
+        let problems = backup.validate();
+        assert!(problems.contains(&"set either bundle or manifest_file, not both".to_string()));
+        assert!(problems.contains(&"after_send can't be used with bundle".to_string()));
+        assert!(problems.contains(&"dedup = \"hash\" can't be used with bundle".to_string()));
+
+        let backup = BackupConfig {
+            after_send: Some("keep".to_string()),
+            ..valid
+        };
+        assert!(backup.validate().is_empty(), "{:?}", backup.validate());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_webhook_url() {
+        for url in [
+            "https://discord.com/api/webhooks/123/abc-DEF_456",
+            "https://discord.com/api/v10/webhooks/123/abc/",
+            "https://ptb.discord.com/api/webhooks/123/abc",
+        ] {
+            assert_eq!(validate_webhook_url(url), Ok(()), "{url}");
+        }
+
+        for (url, reason) in [
+            ("https://discord.gg/abcdef", "invite link"),
+            ("https://discord.com/invite/abcdef", "invite link"),
+            ("https://discord.com/channels/1/2", "channel link"),
+            ("http://discord.com/api/webhooks/1/abc", "must use https"),
+            ("https://discord.com/api/webhooks/1", "does not match"),
+            (
+                "https://discord.com/api/webhooks/abc/def",
+                "must be numeric",
+            ),
+            ("discord.com/api/webhooks/1/abc", "not a valid URL"),
+        ] {
+            let error = validate_webhook_url(url).unwrap_err();
+            assert!(error.contains(reason), "{url}: {error}");
+        }
+    }
+
+    #[test]
+    fn test_config_custom_endpoint_url() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("config.toml");
+        let config = |fields: &str| {
+            format!(
+                r#"
+[[backups]]
+name = "gateway"
+source_directory = "{}"
+file_pattern = "*.sql"
+webhook_url = "https://gateway.internal/hooks/upload"
+{fields}
+"#,
+                temp_dir.path().display()
+            )
+        };
+
+        fs::write(
+            &path,
+            config("form_field = \"attachment\"\ntext_field = \"content\""),
+        )?;
+        let loaded = Config::from_file(&path)?;
+        assert_eq!(loaded.validate(), Vec::<String>::new());
+
+        fs::write(&path, config(""))?;
+        let error = Config::from_file(&path).unwrap_err().to_string();
+        assert!(error.contains("does not match /api/webhooks"), "{error}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_parse_error_kind() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "[[backups]]\nname = ")?;
+
+        let error = Config::from_file(file.path()).unwrap_err();
+        match error.kind() {
+            SenderError::ConfigParse { path, .. } => assert_eq!(path, file.path()),
+            other => panic!("expected ConfigParse, got {other:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_to_file_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let webhook_file = temp_dir.path().join("webhook");
+        fs::write(&webhook_file, "https://discord.com/api/webhooks/3/secret\n")?;
+        let path = temp_dir.path().join("config.toml");
+        fs::write(
+            &path,
+            format!(
+                r#"
+[[backups]]
+name = "db"
+source_directory = "/srv/backups"
+file_pattern = ["*.sql.gz", "*.dump"]
+webhook_url = ["https://discord.com/api/webhooks/1/a", "https://discord.com/api/webhooks/2/b"]
+check_period = "1d"
+archive = "zip"
+on_long_message = "embed"
+max_attempts = 5
+use_embed = true
+
+[[backups]]
+name = "logs"
+source_directory = "/var/log"
+file_pattern = "*.log"
+webhook_url_file = {webhook_file:?}
+enabled = false
+"#
+            ),
+        )?;
+        let config = Config::from_file(&path)?;
+
+        let written = temp_dir.path().join("written.toml");
+        config.to_file(&written)?;
+        assert!(!fs::read_to_string(&written)?.contains("secret"));
+        assert_eq!(Config::from_file(&written)?, config);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_rejects_invalid_webhook_url() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(
+            file,
+            r#"
+[[backups]]
+name = "db"
+source_directory = "/srv/backups"
+file_pattern = "*.sql"
+webhook_url = "https://discord.gg/abcdef"
+"#
+        )?;
+
+        let error = Config::from_file(file.path()).unwrap_err();
+        assert!(matches!(error.kind(), SenderError::ConfigInvalid(_)));
+        assert!(error.is_config_error());
+        assert_eq!(
+            error.to_string(),
+            "backup 'db': webhook_url is an invite link, not a webhook URL"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_rejects_invalid_mention() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(
+            file,
+            r#"
+[[backups]]
+name = "db"
+source_directory = "/srv/backups"
+file_pattern = "*.sql"
+webhook_url = "https://discord.com/api/webhooks/123/abc"
+mention = "123456"
+"#
+        )?;
+
+        let error = Config::from_file(file.path()).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "backup 'db': mention: invalid mention '123456'; expected @here, @everyone, <@user_id> or <@&role_id>"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_multiple_webhook_urls() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(
+            file,
+            r#"
+[[backups]]
+name = "db"
+source_directory = "/srv/backups"
+file_pattern = "*.sql"
+webhook_url = ["https://discord.com/api/webhooks/1/a", "https://discord.com/api/webhooks/2/b"]
+"#
+        )?;
+
+        let config = Config::from_file(file.path())?;
+        assert_eq!(
+            config.backups[0].webhook_url.as_slice(),
+            [
+                "https://discord.com/api/webhooks/1/a",
+                "https://discord.com/api/webhooks/2/b"
+            ]
+        );
+
+        let slack = BackupConfig {
+            target: Some(Target::Slack),
+            webhook_url: WebhookUrls::Multiple(vec![
+                "https://slack.com/api".to_string(),
+                "https://slack.example.com/api".to_string(),
+            ]),
+            ..Default::default()
+        };
+        assert!(slack
+            .validate()
+            .contains(&"multiple webhook_url values are only supported for Discord".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_webhook_url_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let secret = temp_dir.path().join("webhook");
+        fs::write(&secret, "  https://discord.com/api/webhooks/1/secret\n")?;
+
+        let backup = |webhook: &str| {
+            format!(
+                "[[backups]]\nname = \"db\"\nsource_directory = \"/srv\"\nfile_pattern = \"*.sql\"\n{webhook}\n"
+            )
+        };
+        let path = temp_dir.path().join("config.toml");
+
+        fs::write(
+            &path,
+            backup(&format!(
+                "webhook_url_file = {:?}",
+                secret.to_str().unwrap()
+            )),
+        )?;
+        let config = Config::from_file(&path)?;
+        assert_eq!(
+            config.backups[0].webhook_url,
+            "https://discord.com/api/webhooks/1/secret"
+        );
+
+        fs::write(
+            &path,
+            backup(&format!(
+                "webhook_url = \"https://discord.com/api/webhooks/1/inline\"\nwebhook_url_file = {:?}",
+                secret.to_str().unwrap()
+            )),
+        )?;
+        let error = Config::from_file(&path).unwrap_err().to_string();
+        assert!(error.contains("not both"), "{error}");
+
+        fs::write(&path, backup(""))?;
+        let error = Config::from_file(&path).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "backup 'db': webhook_url or webhook_url_file is required"
+        );
+
+        fs::write(&path, backup("webhook_url_file = \"/nonexistent/webhook\""))?;
+        let error = Config::from_file(&path).unwrap_err().to_string();
+        assert!(error.contains("Failed to read webhook_url_file"), "{error}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_expands_env_vars() -> Result<()> {
+        std::env::set_var(
+            "LATEST_SENDER_TEST_WEBHOOK",
+            "https://discord.com/api/webhooks/1/secret",
+        );
+        std::env::set_var("LATEST_SENDER_TEST_ROOT", "/srv");
+
+        let mut file = NamedTempFile::new()?;
+        writeln!(
+            file,
+            r#"
+[[backups]]
+name = "prod"
+source_directory = "${{LATEST_SENDER_TEST_ROOT}}/backups"
+file_pattern = "*.sql"
+webhook_url = "${{LATEST_SENDER_TEST_WEBHOOK}}"
+headers = {{ Authorization = "Bearer ${{LATEST_SENDER_TEST_ROOT}}" }}
+"#
+        )?;
+
+        let config = Config::from_file(file.path())?;
+        assert_eq!(config.backups[0].source_directory, "/srv/backups");
+        assert_eq!(
+            config.backups[0].headers.as_ref().unwrap()["Authorization"],
+            "Bearer /srv"
+        );
+        assert_eq!(
+            config.backups[0].webhook_url,
+            "https://discord.com/api/webhooks/1/secret"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_missing_env_var() -> Result<()> {
+        std::env::remove_var("LATEST_SENDER_TEST_UNSET");
+
+        let mut file = NamedTempFile::new()?;
+        writeln!(
+            file,
+            r#"
+[[backups]]
+name = "prod"
+source_directory = "/srv/backups"
+file_pattern = "*.sql"
+webhook_url = "${{LATEST_SENDER_TEST_UNSET}}"
+"#
+        )?;
+
+        let error = Config::from_file(file.path()).unwrap_err().to_string();
+        assert!(error.contains("backup 'prod'"));
+        assert!(error.contains("LATEST_SENDER_TEST_UNSET"));
 
-impl Config {
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let content = fs::read_to_string(path)?;
-        let config = toml::from_str(&content)?;
-        Ok(config)
+        Ok(())
     }
-}
 
-impl BackupConfig {
-    pub fn parse_check_period(&self) -> Result<Option<Duration>> {
-        match &self.check_period {
-            None => Ok(None),
-            Some(period_str) => {
-                let period = parse_duration_string(period_str)?;
-                Ok(Some(period))
-            }
-        }
+    #[test]
+    fn test_expand_env() -> Result<()> {
+        std::env::set_var("LATEST_SENDER_TEST_A", "a");
+
+        assert_eq!(expand_env("plain")?, "plain");
+        assert_eq!(expand_env("x-${LATEST_SENDER_TEST_A}-y")?, "x-a-y");
+        assert!(expand_env("${LATEST_SENDER_TEST_A").is_err());
+
+        Ok(())
     }
-}
 
-fn parse_duration_string(duration_str: &str) -> Result<Duration> {
-    let std_duration = humantime::parse_duration(duration_str)
-        .map_err(|e| anyhow!("Invalid duration format '{duration_str}': {e}"))?;
+    #[test]
+    fn test_config_rejects_unknown_template_placeholder() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(
+            file,
+            r#"
+[[backups]]
+name = "prod"
+source_directory = "/srv/backups"
+file_pattern = "*.sql"
+webhook_url = "https://discord.com/api/webhooks/1/token"
+message_template = "{{name}} {{hostname}}"
+"#
+        )?;
+
+        let error = Config::from_file(file.path()).unwrap_err();
+        assert!(error.kind().is_config_error());
+        let error = error.to_string();
+        assert!(error.contains("backup 'prod': message_template"));
+        assert!(error.contains("{hostname}"));
 
-    // Convert std::time::Duration to chrono::Duration
-    let chrono_duration =
-        Duration::from_std(std_duration).map_err(|e| anyhow!("Duration too large: {e}"))?;
+        Ok(())
+    }
 
-    Ok(chrono_duration)
-}
+    #[test]
+    fn test_config_target() -> Result<()> {
+        let mut file = NamedTempFile::new()?;
+        writeln!(
+            file,
+            r#"
+[[backups]]
+name = "discord"
+source_directory = "/srv/backups"
+file_pattern = "*.sql"
+webhook_url = "https://discord.com/api/webhooks/1/token"
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Write;
-    use tempfile::NamedTempFile;
+[[backups]]
+name = "slack"
+source_directory = "/srv/backups"
+file_pattern = "*.sql"
+target = "slack"
+slack_token = "xoxb-token"
+slack_channel = "C123"
+"#
+        )?;
+
+        let config = Config::from_file(file.path())?;
+        assert_eq!(
+            config.backups[0].target.unwrap_or_default(),
+            Target::Discord
+        );
+        assert_eq!(config.backups[1].target, Some(Target::Slack));
+        assert_eq!(config.backups[1].webhook_url, "");
+
+        let slack = BackupConfig {
+            target: Some(Target::Slack),
+            ..config.backups[1].clone()
+        };
+        assert!(slack
+            .validate()
+            .iter()
+            .all(|p| !p.starts_with("webhook_url")));
+        let missing_token = BackupConfig {
+            slack_token: None,
+            ..slack
+        };
+        assert!(missing_token
+            .validate()
+            .iter()
+            .any(|p| p.starts_with("slack_token is required")));
+
+        let telegram = BackupConfig {
+            target: Some(Target::Telegram),
+            bot_token: Some("123:abc".to_string()),
+            chat_id: Some("-100123".to_string()),
+            webhook_url: Default::default(),
+            ..config.backups[0].clone()
+        };
+        assert!(telegram
+            .validate()
+            .iter()
+            .all(|p| !p.starts_with("webhook_url") && !p.contains("telegram")));
+        let missing_chat = BackupConfig {
+            chat_id: None,
+            use_embed: true,
+            ..telegram
+        };
+        let problems = missing_chat.validate();
+        assert!(problems.contains(&"chat_id is required for target \"telegram\"".to_string()));
+        assert!(problems.contains(&"use_embed is only supported for Discord".to_string()));
+
+        Ok(())
+    }
 
     #[test]
-    fn test_config_from_file() -> Result<()> {
+    fn test_config_disabled_backup() -> Result<()> {
         let mut file = NamedTempFile::new()?;
         writeln!(
             file,
             r#"
 [[backups]]
-name = "test_backup"
-source_directory = "/path/to/source"
-file_pattern = "*.txt"
-webhook_url = "https://discord.com/api/webhooks/test"
+name = "current"
+source_directory = "/srv/backups"
+file_pattern = "*.sql"
+webhook_url = "https://discord.com/api/webhooks/1/token"
 
 [[backups]]
-name = "another_backup"
-source_directory = "/another/path"
-file_pattern = "*.log"
-webhook_url = "https://discord.com/api/webhooks/another"
+name = "legacy"
+source_directory = "/srv/old"
+file_pattern = "*.sql"
+webhook_url = "https://discord.com/api/webhooks/1/token"
+enabled = false
 "#
         )?;
 
         let config = Config::from_file(file.path())?;
-        assert_eq!(config.backups.len(), 2);
-        assert_eq!(config.backups[0].name, "test_backup");
-        assert_eq!(config.backups[0].source_directory, "/path/to/source");
-        assert_eq!(config.backups[0].file_pattern, "*.txt");
+        assert!(config.backups[0].is_enabled());
+        assert!(!config.backups[1].is_enabled());
+        let enabled = BackupConfig {
+            enabled: Some(true),
+            ..config.backups[1].clone()
+        };
+        assert!(enabled.is_enabled());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_formats_parse_identically() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let sources = [
+            (
+                "config.toml",
+                r#"
+[[backups]]
+name = "db"
+source_directory = "/var/backups"
+file_pattern = ["*.sql.gz", "*.dump"]
+webhook_url = "https://discord.com/api/webhooks/1/token"
+check_period = "1d"
+compress = true
+target = "discord"
+"#,
+            ),
+            (
+                "config.yaml",
+                r#"
+backups:
+  - name: db
+    source_directory: /var/backups
+    file_pattern: ["*.sql.gz", "*.dump"]
+    webhook_url: https://discord.com/api/webhooks/1/token
+    check_period: 1d
+    compress: true
+    target: discord
+"#,
+            ),
+            (
+                "config.json",
+                r#"
+{
+  "backups": [
+    {
+      "name": "db",
+      "source_directory": "/var/backups",
+      "file_pattern": ["*.sql.gz", "*.dump"],
+      "webhook_url": "https://discord.com/api/webhooks/1/token",
+      "check_period": "1d",
+      "compress": true,
+      "target": "discord"
+    }
+  ]
+}
+"#,
+            ),
+        ];
+
+        let mut configs = Vec::new();
+        for (name, content) in sources {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, content)?;
+            configs.push(Config::from_file(&path)?);
+        }
+
+        assert_eq!(configs[0].backups.len(), 1);
+        assert_eq!(configs[0].backups[0].check_period.as_deref(), Some("1d"));
+        assert_eq!(configs[0], configs[1]);
+        assert_eq!(configs[0], configs[2]);
+
+        let yml = temp_dir.path().join("config.yml");
+        fs::write(&yml, sources[1].1)?;
+        assert_eq!(Config::from_file(&yml)?, configs[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_format() -> anyhow::Result<()> {
+        let mut backup: BackupConfig = toml::from_str(
+            r#"
+name = "db"
+source_directory = "/var/backups"
+file_pattern = "*.sql"
+archive = "zip"
+compress_min_size = 1024
+"#,
+        )?;
+        assert_eq!(backup.archive_format(), ArchiveFormat::Zip);
+        let options = backup.send_options()?;
+        assert_eq!(options.archive, ArchiveFormat::Zip);
+        assert_eq!(options.compress_min_size, Some(1024));
+
+        backup.archive = Some(ArchiveFormat::None);
+        assert_eq!(backup.send_options()?.compress_min_size, None);
+
+        backup.archive = None;
+        assert_eq!(backup.archive_format(), ArchiveFormat::None);
+        backup.compress = true;
+        assert_eq!(backup.archive_format(), ArchiveFormat::Gzip);
+
+        backup.archive = Some(ArchiveFormat::Zip);
+        assert!(backup
+            .validate()
+            .contains(&"set either compress or archive, not both".to_string()));
+
+        assert!(toml::from_str::<BackupConfig>("archive = \"rar\"").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_unsupported_extension() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("config.ini");
+        fs::write(&path, "")?;
+
+        let error = Config::from_file(&path).unwrap_err().to_string();
+        assert!(error.contains("'.ini'"));
+        assert!(error.contains(".toml, .yaml, .yml and .json"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_template_parses() -> anyhow::Result<()> {
+        let config: Config = toml::from_str(CONFIG_TEMPLATE)?;
+        assert_eq!(config.backups.len(), 1);
+        assert_eq!(config.backups[0].name, "database_backup");
+        assert!(config.backups[0].parse_check_period()?.is_some());
+        assert!(CONFIG_TEMPLATE.contains("\n# summary_webhook = "));
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_template_mentions_every_field() -> anyhow::Result<()> {
+        let fields = serde_json::to_value(BackupConfig::default())?;
+        for field in fields.as_object().unwrap().keys() {
+            assert!(
+                CONFIG_TEMPLATE.contains(&format!("\n{field} = "))
+                    || CONFIG_TEMPLATE.contains(&format!("\n# {field} = ")),
+                "{field} is missing from the config template"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_relative_source_directory_resolved_against_config_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_dir = temp_dir.path().join("conf");
+        fs::create_dir(&config_dir)?;
+        let config_path = config_dir.join("config.toml");
+        fs::write(
+            &config_path,
+            r#"
+[[backups]]
+name = "relative"
+source_directory = "../backups"
+file_pattern = "*.sql"
+webhook_url = "https://discord.com/api/webhooks/1/test"
+ca_cert_file = "certs/ca.pem"
+
+[[backups]]
+name = "absolute"
+source_directory = "/var/backups"
+file_pattern = "*.sql"
+webhook_url = "https://discord.com/api/webhooks/2/test"
+"#,
+        )?;
+
+        // The working directory is never consulted, so the result is the
+        // same wherever the process was started from.
+        let config = Config::from_file(&config_path)?;
+        assert_eq!(
+            Path::new(config.backups[0].source_directory.first()),
+            config_dir.join("../backups")
+        );
+        assert_eq!(config.backups[1].source_directory, "/var/backups");
+        assert_eq!(
+            config.backups[0].ca_cert_file.as_deref().map(Path::new),
+            Some(config_dir.join("certs/ca.pem").as_path())
+        );
+
+        fs::create_dir(temp_dir.path().join("backups"))?;
+        fs::write(temp_dir.path().join("backups/db.sql"), "dump")?;
+        let found = crate::file_finder::FileFinder::find_latest_file_in_dirs(
+            config.backups[0].source_directory.as_slice(),
+            config.backups[0].file_pattern.as_slice(),
+            &config.backups[0].search_options()?,
+        )?;
+        assert!(found.is_some_and(|(path, ..)| path.ends_with("db.sql")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_relative_webhook_url_file_resolved_against_config_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_dir = temp_dir.path().join("conf");
+        fs::create_dir_all(config_dir.join("secrets"))?;
+        fs::write(
+            config_dir.join("secrets/webhook"),
+            "https://discord.com/api/webhooks/1/secret\n",
+        )?;
+        let config_path = config_dir.join("config.toml");
+        fs::write(
+            &config_path,
+            r#"
+[[backups]]
+name = "db"
+source_directory = "/srv/backups"
+file_pattern = "*.sql"
+webhook_url_file = "secrets/webhook"
+"#,
+        )?;
+
+        // Tests run from the crate root, so this only loads if the path is
+        // taken relative to the config file rather than the working directory.
+        assert_ne!(std::env::current_dir()?, config_dir);
+        let config = Config::from_file(&config_path)?;
         assert_eq!(
             config.backups[0].webhook_url,
-            "https://discord.com/api/webhooks/test"
+            "https://discord.com/api/webhooks/1/secret"
         );
 
-        assert_eq!(config.backups[1].name, "another_backup");
-        assert_eq!(config.backups[1].source_directory, "/another/path");
-        assert_eq!(config.backups[1].file_pattern, "*.log");
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiple_source_directories() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            r#"
+[[backups]]
+name = "db"
+source_directory = ["/mnt/a/backups", "b/backups"]
+file_pattern = "*.sql"
+webhook_url = "https://discord.com/api/webhooks/1/test"
+"#,
+        )?;
+
+        let config = Config::from_file(&config_path)?;
+        let dirs = &config.backups[0].source_directory;
+        assert_eq!(dirs.as_slice().len(), 2);
+        assert_eq!(dirs.as_slice()[0], "/mnt/a/backups");
+        // Relative entries are resolved one by one
         assert_eq!(
-            config.backups[1].webhook_url,
-            "https://discord.com/api/webhooks/another"
+            Path::new(&dirs.as_slice()[1]),
+            temp_dir.path().join("b/backups")
         );
+        assert!(config.backups[0]
+            .validate()
+            .contains(&"source_directory \"/mnt/a/backups\" does not exist".to_string()));
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_duration_string() -> Result<()> {
-        // Test valid formats
-        assert_eq!(parse_duration_string("1d")?, Duration::days(1));
-        assert_eq!(parse_duration_string("24h")?, Duration::hours(24));
-        assert_eq!(parse_duration_string("30m")?, Duration::minutes(30));
-        assert_eq!(parse_duration_string("1w")?, Duration::weeks(1));
+    fn test_config_from_dir() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let backup = |name: &str| {
+            format!(
+                "[[backups]]\nname = \"{name}\"\nsource_directory = \"/srv\"\nfile_pattern = \"*.sql\"\nwebhook_url = \"https://discord.com/api/webhooks/1/token\"\n"
+            )
+        };
+        fs::write(temp_dir.path().join("20-web.toml"), backup("web"))?;
+        fs::write(temp_dir.path().join("10-db.toml"), backup("db"))?;
+        fs::write(
+            temp_dir.path().join("15-broken.toml"),
+            "[[backups]]\nname = ",
+        )?;
+        fs::write(temp_dir.path().join("README.md"), "not a config")?;
+
+        let loaded = Config::from_dir(temp_dir.path())?;
+        let names: Vec<_> = loaded
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["10-db.toml", "15-broken.toml", "20-web.toml"]);
+        assert_eq!(loaded[0].1.as_ref().unwrap().backups[0].name, "db");
+        assert!(loaded[1].1.is_err());
+        assert_eq!(loaded[2].1.as_ref().unwrap().backups[0].name, "web");
+
+        assert!(Config::from_dir(temp_dir.path().join("missing")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_template_refuses_to_overwrite() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("config.toml");
+
+        Config::write_template(&path, false)?;
+        assert_eq!(fs::read_to_string(&path)?, CONFIG_TEMPLATE);
+
+        fs::write(&path, "# mine")?;
+        let error = Config::write_template(&path, false).unwrap_err();
+        assert!(error.to_string().contains("already exists"));
+        assert_eq!(fs::read_to_string(&path)?, "# mine");
+
+        Config::write_template(&path, true)?;
+        assert_eq!(fs::read_to_string(&path)?, CONFIG_TEMPLATE);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mention_for() {
+        let mut backup = BackupConfig {
+            mention: Some("<@&123>".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(backup.mention_for(0), Some("<@&123>"));
+
+        backup.mention_if_larger_than = Some(1024);
+        assert_eq!(backup.mention_for(1024), None);
+        assert_eq!(backup.mention_for(1025), Some("<@&123>"));
+
+        backup.mention = None;
+        assert_eq!(backup.mention_for(4096), None);
+    }
+
+    #[test]
+    fn test_encoding() -> Result<()> {
+        let mut backup = BackupConfig::default();
+        assert_eq!(backup.encoding()?, UTF_8);
+
+        backup.encoding = Some("latin1".to_string());
+        assert_eq!(backup.encoding()?, encoding_rs::WINDOWS_1252);
+        backup.encoding = Some("UTF-16LE".to_string());
+        assert_eq!(backup.encoding()?, encoding_rs::UTF_16LE);
+
+        backup.encoding = Some("klingon".to_string());
+        assert!(backup
+            .validate()
+            .contains(&"encoding: 'klingon' is not a known encoding".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedup() -> Result<()> {
+        let mut backup = BackupConfig::default();
+        assert_eq!(backup.dedup()?, Dedup::Mtime);
+        for (value, expected) in [
+            ("path", Dedup::Path),
+            ("mtime", Dedup::Mtime),
+            ("hash", Dedup::Hash),
+            ("none", Dedup::Off),
+        ] {
+            backup.dedup = Some(value.to_string());
+            assert_eq!(backup.dedup()?, expected);
+        }
+
+        backup.dedup = Some("content".to_string());
+        assert!(backup.validate().contains(
+            &"dedup: 'content' is not one of \"path\", \"mtime\", \"hash\" or \"none\"".to_string()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_by() -> Result<()> {
+        let mut backup = BackupConfig::default();
+        assert_eq!(backup.search_options()?.selection, SelectionStrategy::Mtime);
+
+        backup.select_by = Some("largest".to_string());
         assert_eq!(
-            parse_duration_string("2d 3h")?,
-            Duration::days(2) + Duration::hours(3)
+            backup.search_options()?.selection,
+            SelectionStrategy::LargestSize
         );
+        backup.select_by = Some("smallest".to_string());
+        assert_eq!(backup.selection()?, SelectionStrategy::SmallestSize);
 
-        // Test invalid formats
-        assert!(parse_duration_string("invalid").is_err());
-        assert!(parse_duration_string("").is_err());
+        backup.select_by = Some("biggest".to_string());
+        assert!(backup.validate().contains(
+            &"select_by: 'biggest' is not one of \"mtime\", \"largest\" or \"smallest\""
+                .to_string()
+        ));
 
         Ok(())
     }
 
     #[test]
-    fn test_backup_config_parse_check_period() -> Result<()> {
-        let config = BackupConfig {
-            name: "test".to_string(),
-            source_directory: "/tmp".to_string(),
-            file_pattern: "*.txt".to_string(),
-            webhook_url: "http://example.com".to_string(),
-            check_period: Some("24h".to_string()),
+    fn test_after_send() -> Result<()> {
+        let file = Path::new("/srv/backups/db.sql");
+        let mut backup = BackupConfig {
+            source_directory: "/srv/backups".into(),
+            ..Default::default()
         };
+        assert_eq!(backup.after_send(file)?, AfterSend::Keep);
 
-        let period = config.parse_check_period()?;
-        assert_eq!(period, Some(Duration::hours(24)));
+        backup.after_send = Some("move:sent".to_string());
+        assert_eq!(
+            backup.after_send(file)?,
+            AfterSend::Move(PathBuf::from("/srv/backups/sent"))
+        );
 
-        let config_none = BackupConfig {
-            name: "test".to_string(),
-            source_directory: "/tmp".to_string(),
-            file_pattern: "*.txt".to_string(),
-            webhook_url: "http://example.com".to_string(),
-            check_period: None,
+        // With several directories, relative to the one the file came from
+        backup.source_directory = SourceDirectories::Multiple(vec![
+            "/mnt/a/backups".to_string(),
+            "/mnt/b/backups".to_string(),
+        ]);
+        assert_eq!(
+            backup.after_send(Path::new("/mnt/b/backups/db.sql"))?,
+            AfterSend::Move(PathBuf::from("/mnt/b/backups/sent"))
+        );
+
+        backup.after_send = Some("move:/archive".to_string());
+        assert_eq!(
+            backup.after_send(file)?,
+            AfterSend::Move(PathBuf::from("/archive"))
+        );
+
+        backup.after_send = Some("remove".to_string());
+        assert!(backup
+            .validate()
+            .iter()
+            .any(|p| p.starts_with("after_send: 'remove' is not one of")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ca_cert_file_validation() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let pem = temp_dir.path().join("ca.pem");
+        fs::write(&pem, "not a certificate")?;
+
+        let backup = BackupConfig {
+            ca_cert_file: Some(pem.to_str().unwrap().to_string()),
+            danger_accept_invalid_certs: true,
+            ..Default::default()
         };
+        assert!(backup
+            .validate()
+            .iter()
+            .any(|p| p.starts_with("ca_cert_file") && p.contains("not a PEM certificate")));
+        let options = backup.send_options()?;
+        assert_eq!(options.ca_cert_file, Some(pem.clone()));
+        assert!(options.danger_accept_invalid_certs);
 
-        let period_none = config_none.parse_check_period()?;
-        assert_eq!(period_none, None);
+        let missing = BackupConfig {
+            ca_cert_file: Some("/nonexistent/ca.pem".to_string()),
+            ..Default::default()
+        };
+        assert!(missing
+            .validate()
+            .iter()
+            .any(|p| p.starts_with("ca_cert_file /nonexistent/ca.pem")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_type_validation() -> Result<()> {
+        let backup = BackupConfig {
+            content_type: Some("application/octet-stream".to_string()),
+            ..Default::default()
+        };
+        assert!(backup
+            .validate()
+            .iter()
+            .all(|p| !p.starts_with("content_type")));
+        assert_eq!(
+            backup.send_options()?.content_type.as_deref(),
+            Some("application/octet-stream")
+        );
+
+        let invalid = BackupConfig {
+            content_type: Some("not a mime".to_string()),
+            ..Default::default()
+        };
+        assert!(invalid
+            .validate()
+            .contains(&"content_type 'not a mime' is not a valid MIME type".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_flags() -> Result<()> {
+        let backup = BackupConfig::default();
+        assert_eq!(backup.message_flags(), 0);
+        assert_eq!(backup.send_options()?.flags, 0);
+
+        let silent = BackupConfig {
+            silent: true,
+            ..Default::default()
+        };
+        assert_eq!(silent.send_options()?.flags, FLAG_SUPPRESS_NOTIFICATIONS);
+
+        let both = BackupConfig {
+            suppress_embeds: true,
+            ..silent
+        };
+        assert_eq!(both.message_flags(), 4096 | 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_newer_than() -> Result<()> {
+        let mut backup = BackupConfig {
+            newer_than: Some("2024-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        let parsed = backup.parse_newer_than()?.unwrap();
+        assert_eq!(
+            parsed.with_timezone(&chrono::Utc).to_rfc3339(),
+            "2024-01-01T00:00:00+00:00"
+        );
+
+        backup.newer_than = Some("2024-01-01T09:00:00+09:00".to_string());
+        assert_eq!(backup.parse_newer_than()?, Some(parsed));
+
+        backup.newer_than = Some("2024-01-01".to_string());
+        assert!(backup.parse_newer_than().is_err());
+        assert!(backup
+            .validate()
+            .iter()
+            .any(|p| p.starts_with("newer_than: Invalid RFC 3339 timestamp")));
 
+        backup.newer_than = None;
+        assert_eq!(backup.parse_newer_than()?, None);
         Ok(())
     }
 }
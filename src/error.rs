@@ -0,0 +1,264 @@
+use crate::humanize::format_bytes;
+use reqwest::StatusCode;
+use std::error::Error as StdError;
+use std::fmt;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// `Result` with [`SenderError`], returned by the library's functions.
+pub type Result<T, E = SenderError> = std::result::Result<T, E>;
+
+/// The error of every library function.
+///
+/// Descriptions added on the way up with [`Context`] wrap the original
+/// error; use [`SenderError::kind`] to get it back out of the chain. `{:#}`
+/// prints the whole chain, like `anyhow`.
+#[derive(Debug, Error)]
+pub enum SenderError {
+    /// The config file is not valid TOML, YAML or JSON, or doesn't match the schema.
+    #[error("{message}")]
+    ConfigParse { path: PathBuf, message: String },
+    /// A config value can't be used: a bad template, URL, duration, mention
+    /// or other setting.
+    #[error("{0}")]
+    ConfigInvalid(String),
+    /// A `file_pattern` or `exclude_patterns` entry is not a valid glob.
+    #[error("{field} '{pattern}' is invalid: {message}")]
+    InvalidPattern {
+        field: &'static str,
+        pattern: String,
+        message: String,
+    },
+    #[error("source directory {0} does not exist")]
+    DirectoryMissing(String),
+    /// A file named in a manifest is missing from the source directory.
+    #[error("{path:?} not found in {directory}")]
+    ManifestTargetMissing { directory: String, path: PathBuf },
+    #[error("file {path:?} is {}, exceeds {service} limit of {}", format_bytes(*size), format_bytes(*limit))]
+    FileTooLarge {
+        service: &'static str,
+        path: PathBuf,
+        size: u64,
+        limit: u64,
+    },
+    /// The service answered with a non-success status.
+    #[error("{service} API returned error: {code} - {body}")]
+    HttpStatus {
+        service: &'static str,
+        code: StatusCode,
+        body: String,
+    },
+    /// Another process holds the state file lock.
+    #[error("another instance is running (lock held on {0:?})")]
+    AlreadyRunning(PathBuf),
+    /// The request never got a response: connection, TLS, proxy or timeout.
+    #[error(transparent)]
+    Network(#[from] reqwest::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// Any other failure.
+    #[error("{0}")]
+    Other(String),
+    /// What was being done when `source` happened, added with [`Context`].
+    #[error(fmt = fmt_context)]
+    Context {
+        message: String,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+}
+
+fn fmt_context(
+    message: &str,
+    source: &dyn fmt::Display,
+    f: &mut fmt::Formatter<'_>,
+) -> fmt::Result {
+    if f.alternate() {
+        write!(f, "{message}: {source:#}")
+    } else {
+        f.write_str(message)
+    }
+}
+
+/// Returns early with a [`SenderError::Other`] built like `format!`.
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err($crate::error::SenderError::Other(format!($($arg)*)))
+    };
+}
+pub(crate) use bail;
+
+/// Returns early with a [`SenderError::ConfigInvalid`] built like `format!`.
+macro_rules! bail_config {
+    ($($arg:tt)*) => {
+        return Err($crate::error::SenderError::ConfigInvalid(format!($($arg)*)))
+    };
+}
+pub(crate) use bail_config;
+
+/// Adds a description of what was being done to an error, wrapping it in
+/// [`SenderError::Context`].
+pub trait Context<T> {
+    fn context<C: fmt::Display>(self, context: C) -> Result<T>;
+
+    fn with_context<C: fmt::Display, F: FnOnce() -> C>(self, f: F) -> Result<T>;
+}
+
+impl<T, E: StdError + Send + Sync + 'static> Context<T> for std::result::Result<T, E> {
+    fn context<C: fmt::Display>(self, context: C) -> Result<T> {
+        self.with_context(|| context)
+    }
+
+    fn with_context<C: fmt::Display, F: FnOnce() -> C>(self, f: F) -> Result<T> {
+        self.map_err(|error| SenderError::Context {
+            message: f().to_string(),
+            source: into_source(Box::new(error)),
+        })
+    }
+}
+
+/// Turns I/O and HTTP client errors into their [`SenderError`] variants so
+/// [`SenderError::kind`] can see through the description to them.
+fn into_source(error: Box<dyn StdError + Send + Sync>) -> Box<dyn StdError + Send + Sync> {
+    let error = match error.downcast::<std::io::Error>() {
+        Ok(error) => return Box::new(SenderError::Io(*error)),
+        Err(error) => error,
+    };
+    match error.downcast::<reqwest::Error>() {
+        Ok(error) => Box::new(SenderError::Network(*error)),
+        Err(error) => error,
+    }
+}
+
+/// `None` becomes a [`SenderError::Other`] with the description.
+impl<T> Context<T> for Option<T> {
+    fn context<C: fmt::Display>(self, context: C) -> Result<T> {
+        self.ok_or_else(|| SenderError::Other(context.to_string()))
+    }
+
+    fn with_context<C: fmt::Display, F: FnOnce() -> C>(self, f: F) -> Result<T> {
+        self.ok_or_else(|| SenderError::Other(f().to_string()))
+    }
+}
+
+impl SenderError {
+    /// Wraps the error in a description of what was being done.
+    pub fn context<C: fmt::Display>(self, context: C) -> Self {
+        SenderError::Context {
+            message: context.to_string(),
+            source: Box::new(self),
+        }
+    }
+
+    /// The error below any [`SenderError::Context`] descriptions.
+    pub fn kind(&self) -> &SenderError {
+        let mut error = self;
+        while let SenderError::Context { source, .. } = error {
+            match source.downcast_ref() {
+                Some(inner) => error = inner,
+                None => break,
+            }
+        }
+        error
+    }
+
+    /// Whether the config is at fault rather than the environment, so
+    /// re-running won't help until it is fixed.
+    pub fn is_config_error(&self) -> bool {
+        matches!(
+            self,
+            SenderError::ConfigParse { .. }
+                | SenderError::ConfigInvalid(_)
+                | SenderError::InvalidPattern { .. }
+        )
+    }
+
+    /// Whether trying again later may succeed: network failures, rate
+    /// limits and server errors.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SenderError::Network(_) => true,
+            SenderError::HttpStatus { code, .. } => {
+                *code == StatusCode::TOO_MANY_REQUESTS || code.is_server_error()
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind_through_context() {
+        let error = Err::<(), _>(SenderError::HttpStatus {
+            service: "Discord",
+            code: StatusCode::BAD_GATEWAY,
+            body: "upstream".to_string(),
+        })
+        .context("Failed to upload db.sql")
+        .context("backup 'db'")
+        .unwrap_err();
+
+        assert_eq!(error.to_string(), "backup 'db'");
+        assert_eq!(
+            format!("{error:#}"),
+            "backup 'db': Failed to upload db.sql: Discord API returned error: 502 Bad Gateway - upstream"
+        );
+        // The same chain once it reaches an `anyhow::Error` in main
+        assert_eq!(
+            format!("{error:#}"),
+            format!("{:#}", anyhow::Error::from(error))
+        );
+
+        let error = Err::<(), _>(std::io::Error::other("disk full"))
+            .context("Failed to write state file")
+            .unwrap_err();
+        assert!(matches!(error.kind(), SenderError::Io(_)));
+        assert_eq!(
+            format!("{error:#}"),
+            "Failed to write state file: disk full"
+        );
+
+        let kind = SenderError::HttpStatus {
+            service: "Discord",
+            code: StatusCode::BAD_GATEWAY,
+            body: String::new(),
+        }
+        .context("Failed to upload db.sql");
+        assert!(matches!(
+            kind.kind(),
+            SenderError::HttpStatus { code, .. } if *code == StatusCode::BAD_GATEWAY
+        ));
+        assert!(kind.kind().is_retryable());
+
+        let missing = None::<()>.context("slack_token is required").unwrap_err();
+        assert!(
+            matches!(missing.kind(), SenderError::Other(message) if message == "slack_token is required")
+        );
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let status = |code| SenderError::HttpStatus {
+            service: "Discord",
+            code,
+            body: String::new(),
+        };
+        assert!(status(StatusCode::TOO_MANY_REQUESTS).is_retryable());
+        assert!(!status(StatusCode::UNAUTHORIZED).is_retryable());
+        assert!(!SenderError::DirectoryMissing("/srv/backups".to_string()).is_retryable());
+
+        let too_large = SenderError::FileTooLarge {
+            service: "Discord",
+            path: PathBuf::from("/srv/db.sql"),
+            size: 30 * 1024 * 1024,
+            limit: 25 * 1024 * 1024,
+        };
+        assert_eq!(
+            too_large.to_string(),
+            "file \"/srv/db.sql\" is 30.0 MiB, exceeds Discord limit of 25.0 MiB"
+        );
+        assert!(!too_large.is_retryable());
+    }
+}
@@ -1,81 +1,815 @@
-use anyhow::{Context, Result};
-use chrono::{DateTime, Duration, Local};
-use glob::glob;
+use crate::error::{bail, Context, Result, SenderError};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime};
+use glob::{glob_with, MatchOptions, Pattern};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// How the "latest" file is chosen among the matches.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SelectionStrategy {
+    /// Newest filesystem modified time.
+    #[default]
+    Mtime,
+    /// Greatest file name in lexical order.
+    FilenameLexical,
+    /// Newest timestamp embedded in the file name, parsed with a chrono format string.
+    FilenameTimestamp { format: String },
+    /// Largest file; equal sizes prefer the newest.
+    LargestSize,
+    /// Smallest file; equal sizes prefer the newest.
+    SmallestSize,
+}
+
+/// Name of the ignore file looked up in the search directory by default.
+pub const DEFAULT_IGNORE_FILE: &str = ".senderignore";
+
+/// Filters applied while searching for candidate files.
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// Only accept the latest file if it was modified within this period.
+    pub check_period: Option<Duration>,
+    /// Only accept files modified after this time. Combined with
+    /// `check_period`, the later of the two cutoffs applies.
+    pub newer_than: Option<DateTime<Local>>,
+    /// Glob patterns matched against the file name; matching files are never selected.
+    pub exclude_patterns: Vec<String>,
+    /// Search subdirectories of the directory as well. Directories themselves
+    /// are never returned.
+    pub recursive: bool,
+    /// Treat symlinks as the file they point to. When false, symlinks are skipped.
+    pub follow_symlinks: bool,
+    /// Files smaller than this many bytes are never selected.
+    pub min_size_bytes: Option<u64>,
+    /// Fall back to the next candidate when the preferred file cannot be
+    /// opened for reading, e.g. because of its permissions.
+    pub skip_unreadable: bool,
+    /// Match file and exclude patterns ignoring case, so `*.sql.gz` also
+    /// matches `DB.SQL.GZ`.
+    pub case_insensitive: bool,
+    /// File in the search directory with gitignore-style lines; matching
+    /// files are never selected. A missing file is ignored.
+    pub ignore_file: Option<String>,
+    /// How the latest file is picked among the matches.
+    pub selection: SelectionStrategy,
+    /// Reuse glob results from earlier scans while the directory is
+    /// unchanged (`--cache-scans`). Recursive searches are never cached.
+    pub scan_cache: Option<Arc<ScanCache>>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            check_period: None,
+            newer_than: None,
+            exclude_patterns: Vec::new(),
+            recursive: false,
+            follow_symlinks: true,
+            min_size_bytes: None,
+            skip_unreadable: false,
+            case_insensitive: false,
+            ignore_file: Some(DEFAULT_IGNORE_FILE.to_string()),
+            selection: SelectionStrategy::Mtime,
+            scan_cache: None,
+        }
+    }
+}
+
+impl SearchOptions {
+    /// The earliest modified time a file may have, relative to `now`: the
+    /// stricter (later) of `check_period` and `newer_than`.
+    pub fn cutoff(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        let period_cutoff = self.check_period.map(|period| now - period);
+        period_cutoff.max(self.newer_than)
+    }
+}
+
+/// A matching file with its modified time and size in bytes.
+type Candidate = (PathBuf, DateTime<Local>, u64);
+
+/// Contents of a manifest file written next to the backups, naming the
+/// current backup and a version (or hash) that changes whenever it is
+/// rewritten, e.g. `{"file": "latest.sql.gz", "version": "3f2a..."}`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Manifest {
+    pub file: String,
+    #[serde(alias = "hash")]
+    pub version: String,
+}
+
+/// The file listed in a manifest, with its modified time, size in bytes and
+/// the manifest version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    pub modified: DateTime<Local>,
+    pub size: u64,
+    pub version: String,
+}
+
+/// Glob results kept between scans, keyed by directory, patterns and case
+/// sensitivity. An entry is reused while the directory's modified time is
+/// unchanged, which it is until a file is added, removed or renamed in it.
+/// Only the glob is skipped: the matched files are still stat'ed, so a file
+/// rewritten in place is noticed.
+#[derive(Debug, Default)]
+pub struct ScanCache {
+    entries: Mutex<HashMap<ScanKey, CachedScan>>,
+}
+
+type ScanKey = (String, Vec<String>, bool);
+
+#[derive(Debug, Clone)]
+struct CachedScan {
+    directory_modified: SystemTime,
+    matches: Vec<PathBuf>,
+    unreadable: usize,
+}
+
+/// A directory changed this recently is not cached: with coarse timestamps a
+/// file added right after the scan could leave its modified time unchanged.
+const SCAN_CACHE_MIN_AGE: std::time::Duration = std::time::Duration::from_secs(1);
+
+impl ScanCache {
+    /// The cached glob results for `key` if the directory still has the
+    /// modified time they were taken at, otherwise the result of `scan`,
+    /// which is stored for next time.
+    fn matches(
+        &self,
+        key: ScanKey,
+        directory_modified: SystemTime,
+        scan: impl FnOnce() -> Result<(Vec<PathBuf>, usize)>,
+    ) -> Result<(Vec<PathBuf>, usize)> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(cached) = entries
+            .get(&key)
+            .filter(|cached| cached.directory_modified == directory_modified)
+        {
+            log::debug!("{} unchanged, reusing the previous scan", key.0);
+            return Ok((cached.matches.clone(), cached.unreadable));
+        }
+
+        let (matches, unreadable) = scan()?;
+        let settled = SystemTime::now()
+            .duration_since(directory_modified)
+            .is_ok_and(|age| age >= SCAN_CACHE_MIN_AGE);
+        if settled {
+            entries.insert(
+                key,
+                CachedScan {
+                    directory_modified,
+                    matches: matches.clone(),
+                    unreadable,
+                },
+            );
+        } else {
+            entries.remove(&key);
+        }
+        Ok((matches, unreadable))
+    }
+}
 
 pub struct FileFinder;
 
 impl FileFinder {
     pub fn find_latest_file(directory: &str, pattern: &str) -> Result<Option<PathBuf>> {
-        Self::find_latest_file_with_period(directory, pattern, None)
+        Self::find_latest_file_with_period(directory, &[pattern], None)
     }
 
+    /// Finds the latest file matching any of `patterns`, merging the matches of
+    /// every pattern before picking the newest.
     pub fn find_latest_file_with_period(
         directory: &str,
-        pattern: &str,
+        patterns: &[impl AsRef<str>],
+        check_period: Option<Duration>,
+    ) -> Result<Option<PathBuf>> {
+        let options = SearchOptions {
+            check_period,
+            ..Default::default()
+        };
+        Self::find_latest_file_with_options(directory, patterns, &options)
+    }
+
+    /// Finds the latest file matching any of `patterns`, filtered by `options`.
+    ///
+    /// With `follow_symlinks` set, a symlink is judged by its target's metadata,
+    /// and broken or looping links are skipped with a warning. Without it,
+    /// symlinks are never selected, even if they point at a regular file.
+    pub fn find_latest_file_with_options(
+        directory: &str,
+        patterns: &[impl AsRef<str>],
+        options: &SearchOptions,
+    ) -> Result<Option<PathBuf>> {
+        Ok(Self::find_latest_file_with_meta(directory, patterns, options)?.map(|(path, ..)| path))
+    }
+
+    /// Like [`Self::find_latest_file_with_options`], but also returns the
+    /// modified time and size in bytes read during the scan.
+    ///
+    /// The file is picked by `options.selection`, the newest by default.
+    pub fn find_latest_file_with_meta(
+        directory: &str,
+        patterns: &[impl AsRef<str>],
+        options: &SearchOptions,
+    ) -> Result<Option<(PathBuf, DateTime<Local>, u64)>> {
+        Self::find_latest_file_in_dirs(&[directory], patterns, options)
+    }
+
+    /// Like [`Self::find_latest_file_with_meta`], merging the matches found
+    /// in each of `directories` before picking one.
+    pub fn find_latest_file_in_dirs(
+        directories: &[impl AsRef<str>],
+        patterns: &[impl AsRef<str>],
+        options: &SearchOptions,
+    ) -> Result<Option<(PathBuf, DateTime<Local>, u64)>> {
+        if options.selection == SelectionStrategy::Mtime {
+            return Self::find_by_modified_time(directories, patterns, options, Ordering::Greater);
+        }
+
+        let now = Local::now();
+        let cutoff_time = options.cutoff(now);
+        let candidates: Vec<_> = Self::collect_from_dirs(directories, patterns, options)?
+            .into_iter()
+            .filter(|(path, modified_time, _)| is_recent(path, *modified_time, cutoff_time, now))
+            .collect();
+        Ok(rank_candidates(candidates, &options.selection)
+            .into_iter()
+            .find(|(path, ..)| !options.skip_unreadable || is_readable(path)))
+    }
+
+    /// Every file in `directories` matching any of `patterns` that passes
+    /// `options`' filters, oldest first, with its modified time and size.
+    pub fn find_all_files_in_dirs(
+        directories: &[impl AsRef<str>],
+        patterns: &[impl AsRef<str>],
+        options: &SearchOptions,
+    ) -> Result<Vec<(PathBuf, DateTime<Local>, u64)>> {
+        let now = Local::now();
+        let cutoff_time = options.cutoff(now);
+        let mut candidates: Vec<_> = Self::collect_from_dirs(directories, patterns, options)?
+            .into_iter()
+            .filter(|(path, modified_time, _)| is_recent(path, *modified_time, cutoff_time, now))
+            .filter(|(path, ..)| !options.skip_unreadable || is_readable(path))
+            .collect();
+        candidates.sort_by(|(a_path, a_time, _), (b_path, b_time, _)| {
+            a_time.cmp(b_time).then_with(|| a_path.cmp(b_path))
+        });
+        Ok(candidates)
+    }
+
+    pub fn find_oldest_file(directory: &str, pattern: &str) -> Result<Option<PathBuf>> {
+        Self::find_oldest_file_with_period(directory, &[pattern], None)
+    }
+
+    /// Finds the oldest file matching any of `patterns`. With `check_period`,
+    /// only files modified within that period are considered.
+    pub fn find_oldest_file_with_period(
+        directory: &str,
+        patterns: &[impl AsRef<str>],
         check_period: Option<Duration>,
     ) -> Result<Option<PathBuf>> {
-        let search_pattern = if Path::new(directory).is_absolute() {
-            format!("{directory}/{pattern}")
+        let options = SearchOptions {
+            check_period,
+            ..Default::default()
+        };
+        Self::find_oldest_file_with_options(directory, patterns, &options)
+    }
+
+    /// Finds the oldest file matching any of `patterns`, filtered by `options`.
+    pub fn find_oldest_file_with_options(
+        directory: &str,
+        patterns: &[impl AsRef<str>],
+        options: &SearchOptions,
+    ) -> Result<Option<PathBuf>> {
+        Ok(
+            Self::find_by_modified_time(&[directory], patterns, options, Ordering::Less)?
+                .map(|(path, ..)| path),
+        )
+    }
+
+    /// Picks the candidate whose modified time compares as `preferred` against
+    /// every other one (`Greater` for the newest, `Less` for the oldest).
+    /// Equal modified times are broken by file name in the same direction, so
+    /// the newest pick is the greatest name and the oldest pick the smallest.
+    /// Files modified before `options.cutoff()` are never selected.
+    fn find_by_modified_time(
+        directories: &[impl AsRef<str>],
+        patterns: &[impl AsRef<str>],
+        options: &SearchOptions,
+        preferred: Ordering,
+    ) -> Result<Option<(PathBuf, DateTime<Local>, u64)>> {
+        let now = Local::now();
+        let cutoff_time = options.cutoff(now);
+        let mut candidates: Vec<_> = Self::collect_from_dirs(directories, patterns, options)?
+            .into_iter()
+            .filter(|(path, modified_time, _)| is_recent(path, *modified_time, cutoff_time, now))
+            .collect();
+
+        candidates.sort_by(|(a_path, a_time, _), (b_path, b_time, _)| {
+            let order = a_time
+                .cmp(b_time)
+                .then_with(|| a_path.file_name().cmp(&b_path.file_name()));
+            match preferred {
+                Ordering::Less => order,
+                _ => order.reverse(),
+            }
+        });
+
+        Ok(candidates
+            .into_iter()
+            .find(|(path, ..)| !options.skip_unreadable || is_readable(path)))
+    }
+
+    /// Returns up to `count` matching files sorted newest-first.
+    ///
+    /// Files with identical modified times are ordered by file name (descending)
+    /// so the result is deterministic.
+    pub fn find_latest_files(directory: &str, pattern: &str, count: usize) -> Result<Vec<PathBuf>> {
+        let mut candidates =
+            Self::collect_candidates(directory, &[pattern], &SearchOptions::default())?;
+
+        candidates.sort_by(|(a_path, a_time, _), (b_path, b_time, _)| {
+            b_time
+                .cmp(a_time)
+                .then_with(|| b_path.file_name().cmp(&a_path.file_name()))
+        });
+        candidates.truncate(count);
+
+        Ok(candidates.into_iter().map(|(path, ..)| path).collect())
+    }
+
+    /// Finds the latest matching file according to `strategy`.
+    ///
+    /// With `FilenameTimestamp`, files whose names contain no timestamp in the given
+    /// format are skipped with a warning.
+    pub fn find_latest_file_by(
+        directory: &str,
+        pattern: &str,
+        strategy: &SelectionStrategy,
+    ) -> Result<Option<PathBuf>> {
+        let candidates =
+            Self::collect_candidates(directory, &[pattern], &SearchOptions::default())?;
+
+        Ok(rank_candidates(candidates, strategy)
+            .into_iter()
+            .next()
+            .map(|(path, ..)| path))
+    }
+
+    /// Reads `manifest_file` in `directory` and returns the file it lists.
+    ///
+    /// Returns `None` when the manifest doesn't exist; a manifest that can't
+    /// be parsed or lists a missing file is an error.
+    pub fn find_from_manifest(
+        directory: &str,
+        manifest_file: &str,
+    ) -> Result<Option<ManifestEntry>> {
+        let manifest_path = Path::new(directory).join(manifest_file);
+        let content = match fs::read_to_string(&manifest_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read manifest {manifest_path:?}"))
+            }
+        };
+        let manifest: Manifest = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse manifest {manifest_path:?}"))?;
+
+        let listed = Path::new(&manifest.file);
+        if listed
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            bail!(
+                "Manifest {manifest_path:?} lists {listed:?}, which is not a relative path inside {directory}"
+            );
+        }
+        let path = Path::new(directory).join(listed);
+        let metadata = match fs::metadata(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(SenderError::ManifestTargetMissing {
+                    directory: directory.to_string(),
+                    path,
+                })
+                .with_context(|| format!("Manifest {manifest_path:?} lists missing file"));
+            }
+            metadata => metadata?,
+        };
+        if !metadata.is_file() {
+            bail!("Manifest {manifest_path:?} lists {path:?}, which is not a file");
+        }
+        // Symlinks may still lead out of the directory
+        let canonical_directory = fs::canonicalize(directory)?;
+        if !fs::canonicalize(&path)?.starts_with(&canonical_directory) {
+            bail!("Manifest {manifest_path:?} lists {path:?}, which is outside {directory}");
+        }
+        let modified = metadata
+            .modified()
+            .with_context(|| format!("Failed to read modified time of {path:?}"))?;
+        Ok(Some(ManifestEntry {
+            path,
+            modified: modified.into(),
+            size: metadata.len(),
+            version: manifest.version,
+        }))
+    }
+
+    /// The candidates of every directory, each file listed once even when
+    /// directories overlap.
+    fn collect_from_dirs(
+        directories: &[impl AsRef<str>],
+        patterns: &[impl AsRef<str>],
+        options: &SearchOptions,
+    ) -> Result<Vec<Candidate>> {
+        let mut candidates: Vec<Candidate> = Vec::new();
+        for directory in directories {
+            for candidate in Self::collect_candidates(directory.as_ref(), patterns, options)? {
+                if !candidates.iter().any(|(path, ..)| path == &candidate.0) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+        Ok(candidates)
+    }
+
+    /// Matching files with their modified time and size. Glob entries that
+    /// can't be read are logged one by one at debug level and summarised in a
+    /// single warning, so they don't pass silently as "no files found".
+    fn collect_candidates(
+        directory: &str,
+        patterns: &[impl AsRef<str>],
+        options: &SearchOptions,
+    ) -> Result<Vec<Candidate>> {
+        let (candidates, unreadable) = Self::scan_candidates(directory, patterns, options)?;
+        if unreadable > 0 {
+            log::warn!("{unreadable} entries under {directory} were unreadable and skipped");
+        }
+        Ok(candidates)
+    }
+
+    /// Returns the candidates and the number of unreadable glob entries.
+    fn scan_candidates(
+        directory: &str,
+        patterns: &[impl AsRef<str>],
+        options: &SearchOptions,
+    ) -> Result<(Vec<Candidate>, usize)> {
+        // Checked before the directory so a typo is reported as a config problem
+        for pattern in patterns {
+            compile_pattern("file_pattern", pattern.as_ref())?;
+        }
+        let exclude_patterns = options
+            .exclude_patterns
+            .iter()
+            .map(|p| compile_pattern("exclude_patterns", p))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+
+        // Otherwise a typo in the directory looks like "no files found"
+        let directory_modified = match fs::metadata(directory) {
+            Ok(metadata) if metadata.is_dir() => metadata.modified().ok(),
+            Ok(_) => bail!("source directory {directory} is not a directory"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(SenderError::DirectoryMissing(directory.to_string()))
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read source directory {directory}"))
+            }
+        };
+
+        let match_options = MatchOptions {
+            case_sensitive: !options.case_insensitive,
+            ..Default::default()
+        };
+        let root = if Path::new(directory).is_absolute() {
+            PathBuf::from(directory)
         } else {
-            format!(
-                "{}/{}/{}",
-                std::env::current_dir()?.display(),
-                directory,
-                pattern
-            )
-        };
-
-        let mut latest_file: Option<(PathBuf, DateTime<Local>)> = None;
-
-        for entry in glob(&search_pattern).context("Failed to read glob pattern")? {
-            match entry {
-                Ok(path) => {
-                    let metadata = fs::metadata(&path)
-                        .with_context(|| format!("Failed to get metadata for {path:?}"))?;
-
-                    if metadata.is_file() {
-                        let modified = metadata
-                            .modified()
-                            .with_context(|| format!("Failed to get modified time for {path:?}"))?;
-                        let modified_time: DateTime<Local> = modified.into();
-
-                        match &latest_file {
-                            None => latest_file = Some((path, modified_time)),
-                            Some((_, current_latest)) => {
-                                if modified_time > *current_latest {
-                                    latest_file = Some((path, modified_time));
-                                }
-                            }
+            std::env::current_dir()?.join(directory)
+        };
+        let ignore = load_ignore_file(&root, options)?;
+        let mut candidates = Vec::new();
+
+        let patterns: Vec<String> = patterns
+            .iter()
+            .flat_map(|p| expand_braces(p.as_ref()))
+            .collect();
+        let glob = || Self::glob_matches(directory, &patterns, options.recursive, match_options);
+        let (matches, unreadable) = match (&options.scan_cache, directory_modified) {
+            (Some(cache), Some(modified)) if !options.recursive => {
+                let key = (
+                    directory.to_string(),
+                    patterns.clone(),
+                    options.case_insensitive,
+                );
+                cache.matches(key, modified, glob)?
+            }
+            _ => glob()?,
+        };
+
+        for path in matches {
+            let excluded = path.file_name().and_then(|n| n.to_str()).is_some_and(|n| {
+                exclude_patterns
+                    .iter()
+                    .any(|p| p.matches_with(n, match_options))
+            });
+            let ignored = ignore.as_ref().is_some_and(|(ignore_path, ignore)| {
+                path == *ignore_path || ignore.matched_path_or_any_parents(&path, false).is_ignore()
+            });
+            if excluded || ignored {
+                continue;
+            }
+
+            let metadata = if options.follow_symlinks {
+                match fs::metadata(&path) {
+                    Ok(metadata) => metadata,
+                    Err(e) if path.is_symlink() => {
+                        log::warn!("Skipping broken symlink {path:?}: {e}");
+                        continue;
+                    }
+                    Err(e) => {
+                        return Err(e)
+                            .with_context(|| format!("Failed to get metadata for {path:?}"))
+                    }
+                }
+            } else {
+                fs::symlink_metadata(&path)
+                    .with_context(|| format!("Failed to get metadata for {path:?}"))?
+            };
+
+            let too_small = options
+                .min_size_bytes
+                .is_some_and(|min_size| metadata.len() < min_size);
+
+            if metadata.is_file() && !too_small {
+                let modified = metadata
+                    .modified()
+                    .with_context(|| format!("Failed to get modified time for {path:?}"))?;
+                let modified_time: DateTime<Local> = modified.into();
+
+                candidates.push((path, modified_time, metadata.len()));
+            }
+        }
+
+        Ok((candidates, unreadable))
+    }
+
+    /// Paths in `directory` matching any of `patterns` (already brace
+    /// expanded), each listed once, and the number of unreadable entries.
+    fn glob_matches(
+        directory: &str,
+        patterns: &[String],
+        recursive: bool,
+        match_options: MatchOptions,
+    ) -> Result<(Vec<PathBuf>, usize)> {
+        let mut matches = Vec::new();
+        let mut unreadable = 0;
+        let mut seen = HashSet::new();
+
+        for pattern in patterns {
+            // `**/` also matches zero directories, so top-level files are kept
+            let pattern = if recursive {
+                format!("**/{pattern}")
+            } else {
+                pattern.to_string()
+            };
+            let search_pattern = if Path::new(directory).is_absolute() {
+                format!("{directory}/{pattern}")
+            } else {
+                format!(
+                    "{}/{}/{}",
+                    std::env::current_dir()?.display(),
+                    directory,
+                    pattern
+                )
+            };
+
+            for entry in
+                glob_with(&search_pattern, match_options).context("Failed to read glob pattern")?
+            {
+                match entry {
+                    // A file matched by several patterns is only considered once
+                    Ok(path) => {
+                        if seen.insert(path.clone()) {
+                            matches.push(path);
                         }
                     }
+                    Err(e) => {
+                        log::debug!("Unreadable entry {:?}: {}", e.path(), e.error());
+                        unreadable += 1;
+                    }
                 }
-                Err(e) => eprintln!("Error reading glob entry: {e:?}"),
             }
         }
 
-        // Check if latest file is within the specified period
-        if let (Some((_path, modified_time)), Some(period)) = (&latest_file, check_period) {
-            let now = Local::now();
-            let cutoff_time = now - period;
+        Ok((matches, unreadable))
+    }
+}
+
+/// Compiles every brace expansion of `pattern`. `field` names the config
+/// setting it came from for the error.
+pub fn compile_pattern(field: &'static str, pattern: &str) -> Result<Vec<Pattern>, SenderError> {
+    expand_braces(pattern)
+        .into_iter()
+        .map(|expanded| {
+            Pattern::new(&expanded).map_err(|e| {
+                let mut message = format!("{} near position {}", e.msg, e.pos);
+                if expanded != pattern {
+                    message.push_str(&format!(" of '{expanded}'"));
+                }
+                SenderError::InvalidPattern {
+                    field,
+                    pattern: pattern.to_string(),
+                    message,
+                }
+            })
+        })
+        .collect()
+}
 
-            if *modified_time < cutoff_time {
-                // File is older than the check period, return None
-                return Ok(None);
+/// Expands `{a,b}` groups, which `glob` doesn't support, into one pattern per
+/// alternative: `*.{sql,dump}.gz` becomes `*.sql.gz` and `*.dump.gz`. Groups
+/// may be nested. Braces without a comma or without a closing brace are kept
+/// as literal characters.
+pub fn expand_braces(pattern: &str) -> Vec<String> {
+    let mut depth = 0;
+    let mut open = 0;
+    let mut commas = Vec::new();
+    for (i, c) in pattern.char_indices() {
+        match c {
+            '{' => {
+                if depth == 0 {
+                    open = i;
+                    commas.clear();
+                }
+                depth += 1;
+            }
+            ',' if depth == 1 => commas.push(i),
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 && !commas.is_empty() {
+                    let (prefix, suffix) = (&pattern[..open], &pattern[i + 1..]);
+                    let bounds: Vec<usize> = std::iter::once(open)
+                        .chain(commas.iter().copied())
+                        .chain(std::iter::once(i))
+                        .collect();
+                    let mut expanded: Vec<String> = Vec::new();
+                    for pair in bounds.windows(2) {
+                        let alternative = &pattern[pair[0] + 1..pair[1]];
+                        for p in expand_braces(&format!("{prefix}{alternative}{suffix}")) {
+                            if !expanded.contains(&p) {
+                                expanded.push(p);
+                            }
+                        }
+                    }
+                    return expanded;
+                }
             }
+            _ => {}
+        }
+    }
+    vec![pattern.to_string()]
+}
+
+/// Sorts `candidates` best-first according to `strategy`.
+///
+/// With `FilenameTimestamp`, files whose names contain no timestamp in the
+/// given format are dropped with a warning.
+fn rank_candidates(candidates: Vec<Candidate>, strategy: &SelectionStrategy) -> Vec<Candidate> {
+    let newest_first = |(a_path, a_time, _): &Candidate, (b_path, b_time, _): &Candidate| {
+        b_time
+            .cmp(a_time)
+            .then_with(|| b_path.file_name().cmp(&a_path.file_name()))
+    };
+
+    let mut candidates = candidates;
+    match strategy {
+        SelectionStrategy::Mtime => candidates.sort_by(newest_first),
+        SelectionStrategy::FilenameLexical => {
+            candidates.sort_by(|(a, ..), (b, ..)| b.file_name().cmp(&a.file_name()))
+        }
+        SelectionStrategy::FilenameTimestamp { format } => {
+            let mut stamped: Vec<_> = candidates
+                .into_iter()
+                .filter_map(|candidate| {
+                    let timestamp = candidate
+                        .0
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .and_then(|n| parse_filename_timestamp(n, format));
+                    if timestamp.is_none() {
+                        log::warn!(
+                            "No timestamp matching '{format}' in {:?}, skipping",
+                            candidate.0
+                        );
+                    }
+                    timestamp.map(|timestamp| (timestamp, candidate))
+                })
+                .collect();
+            stamped.sort_by(|(a, _), (b, _)| b.cmp(a));
+            candidates = stamped
+                .into_iter()
+                .map(|(_, candidate)| candidate)
+                .collect();
         }
+        SelectionStrategy::LargestSize => {
+            candidates.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| newest_first(a, b)))
+        }
+        SelectionStrategy::SmallestSize => {
+            candidates.sort_by(|a, b| a.2.cmp(&b.2).then_with(|| newest_first(a, b)))
+        }
+    }
+    candidates
+}
+
+/// Reads `options.ignore_file` from `root`, returning its path and matcher.
+/// Returns `None` when no ignore file is configured or it doesn't exist.
+fn load_ignore_file(root: &Path, options: &SearchOptions) -> Result<Option<(PathBuf, Gitignore)>> {
+    let Some(name) = &options.ignore_file else {
+        return Ok(None);
+    };
+    let path = root.join(name);
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let mut builder = GitignoreBuilder::new(root);
+    builder
+        .case_insensitive(options.case_insensitive)
+        .with_context(|| format!("Invalid ignore file {path:?}"))?;
+    if let Some(e) = builder.add(&path) {
+        return Err(e).with_context(|| format!("Invalid ignore file {path:?}"));
+    }
+    let ignore = builder
+        .build()
+        .with_context(|| format!("Invalid ignore file {path:?}"))?;
+    Ok(Some((path, ignore)))
+}
+
+/// Whether a file modified at `modified` passes `cutoff`.
+///
+/// A modified time in the future, e.g. from a file server whose clock runs
+/// ahead, is compared as `now` and logged as a warning.
+fn is_recent(
+    path: &Path,
+    modified: DateTime<Local>,
+    cutoff: Option<DateTime<Local>>,
+    now: DateTime<Local>,
+) -> bool {
+    if modified > now {
+        log::warn!(
+            "{path:?} has a modified time in the future ({}); treating it as modified now",
+            modified.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+    cutoff.is_none_or(|cutoff| modified.min(now) >= cutoff)
+}
 
-        Ok(latest_file.map(|(path, _)| path))
+/// Whether `path` can be opened for reading; logs a warning when it cannot.
+fn is_readable(path: &Path) -> bool {
+    match fs::File::open(path) {
+        Ok(_) => true,
+        Err(e) => {
+            log::warn!("Skipping unreadable file {path:?}: {e}");
+            false
+        }
     }
 }
 
+/// Finds the first timestamp in `file_name` that matches `format`.
+///
+/// Date-only formats are accepted and treated as midnight. Candidate positions
+/// starting with a sign are skipped so `db-2024` is not read as year -2024.
+fn parse_filename_timestamp(file_name: &str, format: &str) -> Option<NaiveDateTime> {
+    file_name
+        .char_indices()
+        .filter(|(_, c)| *c != '-' && *c != '+')
+        .find_map(|(i, _)| {
+            let rest = &file_name[i..];
+            NaiveDateTime::parse_and_remainder(rest, format)
+                .map(|(timestamp, _)| timestamp)
+                .or_else(|_| {
+                    NaiveDate::parse_and_remainder(rest, format)
+                        .map(|(date, _)| date.and_time(chrono::NaiveTime::MIN))
+                })
+                .ok()
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
     use std::fs::File;
     use std::io::Write;
     use std::thread;
+    use std::time::SystemTime;
     use tempfile::TempDir;
 
     #[test]
@@ -148,7 +882,7 @@ mod tests {
         // Test with 1 hour period - recent file should be found
         let result = FileFinder::find_latest_file_with_period(
             dir_path.to_str().unwrap(),
-            "*.txt",
+            &["*.txt"],
             Some(Duration::hours(1)),
         )?;
 
@@ -172,7 +906,7 @@ mod tests {
         thread::sleep(std::time::Duration::from_millis(10));
         let result = FileFinder::find_latest_file_with_period(
             dir_path.to_str().unwrap(),
-            "*.txt",
+            &["*.txt"],
             Some(Duration::milliseconds(1)),
         )?;
 
@@ -192,11 +926,947 @@ mod tests {
 
         // Test with no period (should behave like original function)
         let result =
-            FileFinder::find_latest_file_with_period(dir_path.to_str().unwrap(), "*.txt", None)?;
+            FileFinder::find_latest_file_with_period(dir_path.to_str().unwrap(), &["*.txt"], None)?;
 
         assert!(result.is_some());
         assert_eq!(result.unwrap(), file_path);
 
         Ok(())
     }
+
+    #[test]
+    fn test_find_latest_files_sorted_newest_first() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        let mut paths = Vec::new();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            let path = dir_path.join(name);
+            let mut file = File::create(&path)?;
+            writeln!(file, "{name}")?;
+            paths.push(path);
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let result = FileFinder::find_latest_files(dir_path.to_str().unwrap(), "*.txt", 2)?;
+        assert_eq!(result, vec![paths[2].clone(), paths[1].clone()]);
+
+        // Asking for more than exists returns every match
+        let result = FileFinder::find_latest_files(dir_path.to_str().unwrap(), "*.txt", 10)?;
+        assert_eq!(
+            result,
+            vec![paths[2].clone(), paths[1].clone(), paths[0].clone()]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_latest_files_tie_broken_by_name() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        let mtime = std::time::SystemTime::now();
+        for name in ["x.txt", "y.txt"] {
+            let file = File::create(dir_path.join(name))?;
+            file.set_modified(mtime)?;
+        }
+
+        let result = FileFinder::find_latest_files(dir_path.to_str().unwrap(), "*.txt", 2)?;
+        assert_eq!(result, vec![dir_path.join("y.txt"), dir_path.join("x.txt")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_latest_file_by_filename_timestamp() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        // Newer timestamp written first so mtime ordering disagrees with the name
+        let newer = dir_path.join("db-2024-01-16T03-00-00.sql.gz");
+        File::create(&newer)?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        File::create(dir_path.join("db-2024-01-15T03-00-00.sql.gz"))?;
+        File::create(dir_path.join("db-unknown.sql.gz"))?;
+
+        let result = FileFinder::find_latest_file_by(
+            dir_path.to_str().unwrap(),
+            "*.sql.gz",
+            &SelectionStrategy::FilenameTimestamp {
+                format: "%Y-%m-%dT%H-%M-%S".to_string(),
+            },
+        )?;
+        assert_eq!(result, Some(newer));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_pattern_error() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_str().unwrap();
+
+        let error = FileFinder::find_latest_file(dir, "*.[sql").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "file_pattern '*.[sql' is invalid: invalid range pattern near position 2"
+        );
+        let kind = error.kind();
+        assert!(matches!(
+            kind,
+            SenderError::InvalidPattern { field: "file_pattern", pattern, .. } if pattern == "*.[sql"
+        ));
+        assert!(kind.is_config_error());
+
+        // Reported before a missing directory, and per brace alternative
+        let error =
+            FileFinder::find_latest_file("/nonexistent/latest-sender", "{a,b[}.sql").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "file_pattern '{a,b[}.sql' is invalid: invalid range pattern near position 1 of 'b[.sql'"
+        );
+
+        let options = SearchOptions {
+            exclude_patterns: vec!["[".to_string()],
+            ..Default::default()
+        };
+        let error =
+            FileFinder::find_latest_file_with_options(dir, &["*.sql"], &options).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("exclude_patterns '[' is invalid: "));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_cache_reused_while_directory_unchanged() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_str().unwrap();
+        let ago = |secs| SystemTime::now() - std::time::Duration::from_secs(secs);
+        let set_modified = |path: &Path, time| -> Result<()> {
+            fs::File::open(path)?.set_modified(time)?;
+            Ok(())
+        };
+        let cache = Arc::new(ScanCache::default());
+        let options = SearchOptions {
+            scan_cache: Some(cache.clone()),
+            ..Default::default()
+        };
+        let scan = || FileFinder::find_latest_file_with_meta(dir, &["*.sql"], &options);
+
+        // Just changed: scanned, but not cached yet
+        fs::write(temp_dir.path().join("a.sql"), "a")?;
+        set_modified(&temp_dir.path().join("a.sql"), ago(100))?;
+        assert_eq!(scan()?.unwrap().0, temp_dir.path().join("a.sql"));
+        assert!(cache.entries.lock().unwrap().is_empty());
+
+        let unchanged = ago(60);
+        set_modified(temp_dir.path(), unchanged)?;
+        scan()?;
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+
+        // A new file behind an unchanged directory mtime is not globbed...
+        fs::write(temp_dir.path().join("b.sql"), "b")?;
+        set_modified(temp_dir.path(), unchanged)?;
+        // ...but cached matches are stat'ed again, so rewrites are seen
+        fs::write(temp_dir.path().join("a.sql"), "rewritten")?;
+        let (path, _, size) = scan()?.unwrap();
+        assert_eq!(path, temp_dir.path().join("a.sql"));
+        assert_eq!(size, 9);
+
+        // Once the directory mtime moves on, the new file is found
+        set_modified(temp_dir.path(), ago(30))?;
+        set_modified(&temp_dir.path().join("a.sql"), ago(100))?;
+        assert_eq!(scan()?.unwrap().0, temp_dir.path().join("b.sql"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_latest_file_in_dirs() -> Result<()> {
+        let server_a = TempDir::new()?;
+        let server_b = TempDir::new()?;
+        let hour_ago = SystemTime::now() - std::time::Duration::from_secs(3600);
+
+        File::create(server_a.path().join("db-1.sql"))?.set_modified(hour_ago)?;
+        let newest = server_b.path().join("db-2.sql");
+        File::create(&newest)?;
+        fs::write(server_b.path().join("db-0.sql"), vec![0u8; 4096])?;
+        File::open(server_b.path().join("db-0.sql"))?.set_modified(hour_ago)?;
+
+        let dirs = [
+            server_a.path().to_str().unwrap(),
+            server_b.path().to_str().unwrap(),
+        ];
+        let (path, ..) =
+            FileFinder::find_latest_file_in_dirs(&dirs, &["*.sql"], &SearchOptions::default())?
+                .expect("a file matches");
+        assert_eq!(path, newest);
+
+        let largest = SearchOptions {
+            selection: SelectionStrategy::LargestSize,
+            ..Default::default()
+        };
+        let (path, _, size) = FileFinder::find_latest_file_in_dirs(&dirs, &["*.sql"], &largest)?
+            .expect("a file matches");
+        assert_eq!((path, size), (server_b.path().join("db-0.sql"), 4096));
+
+        // A directory listed twice doesn't duplicate its files
+        let twice = [dirs[0], dirs[0]];
+        assert_eq!(
+            FileFinder::collect_from_dirs(&twice, &["*.sql"], &SearchOptions::default())?.len(),
+            1
+        );
+
+        // Every directory must exist
+        let missing = [dirs[0], "/nonexistent/latest-sender"];
+        assert!(FileFinder::find_latest_file_in_dirs(
+            &missing,
+            &["*.sql"],
+            &SearchOptions::default()
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_latest_file_by_size() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+        let dir = dir_path.to_str().unwrap();
+
+        let full = dir_path.join("full.dump");
+        fs::write(&full, vec![0u8; 4096])?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        let chunk = dir_path.join("chunk-1.dump");
+        fs::write(&chunk, vec![0u8; 512])?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        fs::write(dir_path.join("chunk-2.dump"), vec![0u8; 1024])?;
+
+        assert_eq!(
+            FileFinder::find_latest_file_by(dir, "*.dump", &SelectionStrategy::LargestSize)?,
+            Some(full.clone())
+        );
+        assert_eq!(
+            FileFinder::find_latest_file_by(dir, "*.dump", &SelectionStrategy::SmallestSize)?,
+            Some(chunk)
+        );
+
+        let options = SearchOptions {
+            selection: SelectionStrategy::LargestSize,
+            ..Default::default()
+        };
+        let (path, _, size) = FileFinder::find_latest_file_with_meta(dir, &["*.dump"], &options)?
+            .expect("a file matches");
+        assert_eq!((path, size), (full, 4096));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_latest_file_by_size_prefers_newest_on_ties() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        fs::write(dir_path.join("b.dump"), "same")?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        let newer = dir_path.join("a.dump");
+        fs::write(&newer, "same")?;
+
+        assert_eq!(
+            FileFinder::find_latest_file_by(
+                dir_path.to_str().unwrap(),
+                "*.dump",
+                &SelectionStrategy::LargestSize
+            )?,
+            Some(newer)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_latest_file_by_filename_lexical() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        let last = dir_path.join("b.txt");
+        File::create(&last)?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        File::create(dir_path.join("a.txt"))?;
+
+        let result = FileFinder::find_latest_file_by(
+            dir_path.to_str().unwrap(),
+            "*.txt",
+            &SelectionStrategy::FilenameLexical,
+        )?;
+        assert_eq!(result, Some(last));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_filename_timestamp() {
+        assert_eq!(
+            parse_filename_timestamp("db-2024-01-15T03-00-00.sql.gz", "%Y-%m-%dT%H-%M-%S"),
+            NaiveDate::from_ymd_opt(2024, 1, 15).and_then(|d| d.and_hms_opt(3, 0, 0))
+        );
+        assert_eq!(
+            parse_filename_timestamp("report_20240115.csv", "%Y%m%d"),
+            NaiveDate::from_ymd_opt(2024, 1, 15).and_then(|d| d.and_hms_opt(0, 0, 0))
+        );
+        assert_eq!(parse_filename_timestamp("report.csv", "%Y%m%d"), None);
+    }
+
+    #[test]
+    fn test_find_latest_file_with_period_multiple_patterns() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        File::create(dir_path.join("older.sql.gz"))?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        let newest = dir_path.join("newest.dump");
+        File::create(&newest)?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        File::create(dir_path.join("ignored.log"))?;
+
+        let result = FileFinder::find_latest_file_with_period(
+            dir_path.to_str().unwrap(),
+            &["*.sql.gz", "*.dump"],
+            None,
+        )?;
+        assert_eq!(result, Some(newest));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_latest_file_with_exclude_patterns() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        let complete = dir_path.join("db.sql.gz");
+        File::create(&complete)?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        File::create(dir_path.join("db.sql.gz.part"))?;
+
+        let options = SearchOptions {
+            exclude_patterns: vec!["*.part".to_string()],
+            ..Default::default()
+        };
+        let result = FileFinder::find_latest_file_with_options(
+            dir_path.to_str().unwrap(),
+            &["db.*"],
+            &options,
+        )?;
+        assert_eq!(result, Some(complete));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_latest_file_recursive() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        File::create(dir_path.join("top.sql.gz"))?;
+        thread::sleep(std::time::Duration::from_millis(10));
+
+        let nested_dir = dir_path.join("2024").join("01").join("15");
+        fs::create_dir_all(&nested_dir)?;
+        let nested = nested_dir.join("db.sql.gz");
+        File::create(&nested)?;
+        thread::sleep(std::time::Duration::from_millis(10));
+
+        // A directory matching the pattern must never be selected
+        fs::create_dir(dir_path.join("dir.sql.gz"))?;
+
+        let options = SearchOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let result = FileFinder::find_latest_file_with_options(
+            dir_path.to_str().unwrap(),
+            &["*.sql.gz"],
+            &options,
+        )?;
+        assert_eq!(result, Some(nested));
+
+        let result = FileFinder::find_latest_file(dir_path.to_str().unwrap(), "*.sql.gz")?;
+        assert_eq!(result, Some(dir_path.join("top.sql.gz")));
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_latest_file_symlinks() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        let target = dir_path.join("db-2024.sql.gz");
+        File::create(&target)?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        std::os::unix::fs::symlink(&target, dir_path.join("current.sql.gz"))?;
+        std::os::unix::fs::symlink(dir_path.join("gone"), dir_path.join("broken.sql.gz"))?;
+
+        // Following: the link counts as a file and the broken link is skipped
+        let result = FileFinder::find_latest_files(dir_path.to_str().unwrap(), "*.sql.gz", 10)?;
+        assert_eq!(result.len(), 2);
+
+        // Not following: only the regular file is considered
+        let options = SearchOptions {
+            follow_symlinks: false,
+            ..Default::default()
+        };
+        let result = FileFinder::find_latest_file_with_options(
+            dir_path.to_str().unwrap(),
+            &["*.sql.gz"],
+            &options,
+        )?;
+        assert_eq!(result, Some(target));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_latest_file_min_size() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        let valid = dir_path.join("good.sql.gz");
+        let mut file = File::create(&valid)?;
+        writeln!(file, "real backup data")?;
+        thread::sleep(std::time::Duration::from_millis(10));
+
+        // The newest file is empty, as left behind by a failed backup job
+        File::create(dir_path.join("failed.sql.gz"))?;
+
+        let options = SearchOptions {
+            min_size_bytes: Some(1),
+            ..Default::default()
+        };
+        let result = FileFinder::find_latest_file_with_options(
+            dir_path.to_str().unwrap(),
+            &["*.sql.gz"],
+            &options,
+        )?;
+        assert_eq!(result, Some(valid));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_source_directory_is_an_error() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let missing = temp_dir.path().join("typo");
+        let missing = missing.to_str().unwrap();
+
+        let error =
+            FileFinder::find_latest_file_with_period(missing, &["*.txt"], None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("source directory {missing} does not exist")
+        );
+
+        let file = temp_dir.path().join("file.txt");
+        File::create(&file)?;
+        let error =
+            FileFinder::find_latest_file_with_period(file.to_str().unwrap(), &["*.txt"], None)
+                .unwrap_err();
+        assert!(error.to_string().contains("is not a directory"));
+
+        // An existing directory without matches is still not an error
+        let result = FileFinder::find_latest_file_with_period(
+            temp_dir.path().to_str().unwrap(),
+            &["*.sql"],
+            None,
+        )?;
+        assert_eq!(result, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_oldest_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        let file1_path = dir_path.join("file1.txt");
+        File::create(&file1_path)?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        File::create(dir_path.join("file2.txt"))?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        File::create(dir_path.join("file3.txt"))?;
+
+        let _other_file = File::create(dir_path.join("other.log"))?;
+
+        let result = FileFinder::find_oldest_file(dir_path.to_str().unwrap(), "*.txt")?;
+        assert_eq!(result, Some(file1_path));
+
+        let result = FileFinder::find_oldest_file(dir_path.to_str().unwrap(), "*.sql")?;
+        assert_eq!(result, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_oldest_file_with_period() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        let expired = File::create(dir_path.join("expired.txt"))?;
+        expired.set_modified(SystemTime::now() - std::time::Duration::from_secs(3 * 3600))?;
+
+        let oldest_in_period = dir_path.join("older.txt");
+        File::create(&oldest_in_period)?
+            .set_modified(SystemTime::now() - std::time::Duration::from_secs(1800))?;
+        File::create(dir_path.join("newest.txt"))?;
+
+        let result = FileFinder::find_oldest_file_with_period(
+            dir_path.to_str().unwrap(),
+            &["*.txt"],
+            Some(Duration::hours(1)),
+        )?;
+        assert_eq!(result, Some(oldest_in_period));
+
+        let result = FileFinder::find_oldest_file_with_period(
+            dir_path.to_str().unwrap(),
+            &["expired.txt"],
+            Some(Duration::hours(1)),
+        )?;
+        assert_eq!(result, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_latest_file_case_insensitive() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        File::create(dir_path.join("old.sql.gz"))?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        let upper = dir_path.join("NEW.SQL.GZ");
+        File::create(&upper)?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        File::create(dir_path.join("Partial.Sql.Gz.PART"))?;
+
+        let dir = dir_path.to_str().unwrap();
+        let sensitive = FileFinder::find_latest_file(dir, "*.sql.gz")?;
+        assert_eq!(sensitive, Some(dir_path.join("old.sql.gz")));
+
+        let options = SearchOptions {
+            case_insensitive: true,
+            exclude_patterns: vec!["*.part".to_string()],
+            ..Default::default()
+        };
+        let insensitive = FileFinder::find_latest_file_with_options(dir, &["*.sql.gz*"], &options)?;
+        assert_eq!(insensitive, Some(upper));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_find_latest_file_skips_unreadable() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+
+        let readable = dir_path.join("older.sql");
+        File::create(&readable)?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        let unreadable = dir_path.join("newer.sql");
+        File::create(&unreadable)?;
+        fs::set_permissions(&unreadable, fs::Permissions::from_mode(0o000))?;
+
+        if File::open(&unreadable).is_ok() {
+            // Running as root; permissions can't make the file unreadable
+            return Ok(());
+        }
+
+        let dir = dir_path.to_str().unwrap();
+        let options = SearchOptions {
+            skip_unreadable: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            FileFinder::find_latest_file_with_options(dir, &["*.sql"], &options)?,
+            Some(readable)
+        );
+        assert_eq!(
+            FileFinder::find_latest_file(dir, "*.sql")?,
+            Some(unreadable)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unreadable_glob_entries_are_counted() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new()?;
+        File::create(temp_dir.path().join("top.sql"))?;
+        let locked = temp_dir.path().join("locked");
+        fs::create_dir(&locked)?;
+        File::create(locked.join("inner.sql"))?;
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000))?;
+
+        if fs::read_dir(&locked).is_ok() {
+            // Running as root; permissions can't make the directory unreadable
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o755))?;
+            return Ok(());
+        }
+
+        let scan = FileFinder::scan_candidates(
+            temp_dir.path().to_str().unwrap(),
+            &["*.sql"],
+            &SearchOptions {
+                recursive: true,
+                ..Default::default()
+            },
+        );
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755))?;
+
+        let (candidates, unreadable) = scan?;
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(unreadable, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_options_cutoff() {
+        let now = Local.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let period = SearchOptions {
+            check_period: Some(Duration::days(1)),
+            ..Default::default()
+        };
+        assert_eq!(period.cutoff(now), Some(now - Duration::days(1)));
+
+        let absolute = Local.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let fixed = SearchOptions {
+            newer_than: Some(absolute),
+            ..Default::default()
+        };
+        assert_eq!(fixed.cutoff(now), Some(absolute));
+
+        // The later cutoff wins, whichever setting it comes from
+        let both = SearchOptions {
+            newer_than: Some(absolute),
+            ..period.clone()
+        };
+        assert_eq!(both.cutoff(now), Some(now - Duration::days(1)));
+        let recent = now - Duration::hours(1);
+        let both = SearchOptions {
+            newer_than: Some(recent),
+            ..period
+        };
+        assert_eq!(both.cutoff(now), Some(recent));
+
+        assert_eq!(SearchOptions::default().cutoff(now), None);
+    }
+
+    #[test]
+    fn test_find_latest_file_newer_than() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+        let path = dir_path.join("db.sql");
+        File::create(&path)?;
+        let dir = dir_path.to_str().unwrap();
+
+        let before = SearchOptions {
+            newer_than: Some(Local::now() - Duration::hours(1)),
+            ..Default::default()
+        };
+        assert_eq!(
+            FileFinder::find_latest_file_with_options(dir, &["*.sql"], &before)?,
+            Some(path)
+        );
+
+        let after = SearchOptions {
+            newer_than: Some(Local::now() + Duration::hours(1)),
+            ..Default::default()
+        };
+        assert_eq!(
+            FileFinder::find_latest_file_with_options(dir, &["*.sql"], &after)?,
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_latest_file_with_meta() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path().to_str().unwrap();
+
+        let old_time = SystemTime::now() - std::time::Duration::from_secs(3600);
+        let old = temp_dir.path().join("old.txt");
+        File::create(&old)?.set_modified(old_time)?;
+        let new = temp_dir.path().join("new.txt");
+        fs::write(&new, "12345")?;
+
+        let (path, modified, size) = FileFinder::find_latest_file_with_meta(
+            dir_path,
+            &["*.txt"],
+            &SearchOptions::default(),
+        )?
+        .expect("a match");
+        assert_eq!(path, new);
+        assert_eq!(
+            modified,
+            DateTime::<Local>::from(fs::metadata(&new)?.modified()?)
+        );
+        assert_eq!(size, 5);
+
+        assert!(FileFinder::find_latest_file_with_meta(
+            dir_path,
+            &["*.csv"],
+            &SearchOptions::default()
+        )?
+        .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_latest_file_senderignore() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path().to_str().unwrap();
+
+        let old_time = SystemTime::now() - std::time::Duration::from_secs(3600);
+        File::create(temp_dir.path().join("db.sql"))?.set_modified(old_time)?;
+        fs::create_dir(temp_dir.path().join("scratch"))?;
+        File::create(temp_dir.path().join("scratch/tmp.sql"))?;
+        File::create(temp_dir.path().join("notes.sql"))?;
+
+        let options = SearchOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        // Without an ignore file the newest match wins as usual
+        let latest = FileFinder::find_latest_file_with_options(dir_path, &["*.sql"], &options)?;
+        assert!(latest.is_some_and(|p| p.ends_with("notes.sql") || p.ends_with("tmp.sql")));
+
+        fs::write(
+            temp_dir.path().join(DEFAULT_IGNORE_FILE),
+            "# auxiliary files\nnotes.sql\nscratch/\n",
+        )?;
+        let latest = FileFinder::find_latest_file_with_options(dir_path, &["*"], &options)?;
+        assert_eq!(latest, Some(temp_dir.path().join("db.sql")));
+
+        let disabled = SearchOptions {
+            ignore_file: None,
+            ..options
+        };
+        let latest = FileFinder::find_latest_file_with_options(dir_path, &["*.sql"], &disabled)?;
+        assert_ne!(latest, Some(temp_dir.path().join("db.sql")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_equal_mtimes_broken_by_file_name() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path().to_str().unwrap();
+
+        let same_time = SystemTime::now() - std::time::Duration::from_secs(60);
+        for name in ["backup_b.sql", "backup_c.sql", "backup_a.sql"] {
+            File::create(temp_dir.path().join(name))?.set_modified(same_time)?;
+        }
+
+        for _ in 0..3 {
+            assert_eq!(
+                FileFinder::find_latest_file(dir_path, "*.sql")?,
+                Some(temp_dir.path().join("backup_c.sql"))
+            );
+            assert_eq!(
+                FileFinder::find_latest_file_by(dir_path, "*.sql", &SelectionStrategy::Mtime)?,
+                Some(temp_dir.path().join("backup_c.sql"))
+            );
+            assert_eq!(
+                FileFinder::find_oldest_file(dir_path, "*.sql")?,
+                Some(temp_dir.path().join("backup_a.sql"))
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_from_manifest() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_str().unwrap();
+
+        assert_eq!(FileFinder::find_from_manifest(dir, "MANIFEST.json")?, None);
+
+        fs::write(temp_dir.path().join("latest.sql.gz"), "dump")?;
+        fs::write(
+            temp_dir.path().join("MANIFEST.json"),
+            r#"{"file": "latest.sql.gz", "hash": "3f2a"}"#,
+        )?;
+        let entry = FileFinder::find_from_manifest(dir, "MANIFEST.json")?.expect("manifest exists");
+        assert_eq!(entry.path, temp_dir.path().join("latest.sql.gz"));
+        assert_eq!(entry.size, 4);
+        assert_eq!(entry.version, "3f2a");
+
+        fs::write(
+            temp_dir.path().join("MANIFEST.json"),
+            r#"{"file": "gone.sql.gz", "version": "1"}"#,
+        )?;
+        let error = FileFinder::find_from_manifest(dir, "MANIFEST.json")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("lists missing file"), "{error}");
+
+        fs::write(temp_dir.path().join("MANIFEST.json"), "not json")?;
+        let error = FileFinder::find_from_manifest(dir, "MANIFEST.json")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Failed to parse manifest"), "{error}");
+
+        for file in [
+            "/etc/passwd",
+            "../outside.sql.gz",
+            "sub/../../outside.sql.gz",
+        ] {
+            fs::write(
+                temp_dir.path().join("MANIFEST.json"),
+                serde_json::json!({ "file": file, "version": "1" }).to_string(),
+            )?;
+            let error = FileFinder::find_from_manifest(dir, "MANIFEST.json")
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains("not a relative path inside"), "{error}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_find_from_manifest_rejects_symlink_outside_directory() -> Result<()> {
+        let outside = TempDir::new()?;
+        fs::write(outside.path().join("secret"), "secret")?;
+        let temp_dir = TempDir::new()?;
+        std::os::unix::fs::symlink(
+            outside.path().join("secret"),
+            temp_dir.path().join("latest.sql.gz"),
+        )?;
+        fs::write(
+            temp_dir.path().join("MANIFEST.json"),
+            r#"{"file": "latest.sql.gz", "version": "1"}"#,
+        )?;
+
+        let error =
+            FileFinder::find_from_manifest(temp_dir.path().to_str().unwrap(), "MANIFEST.json")
+                .unwrap_err()
+                .to_string();
+        assert!(error.contains("which is outside"), "{error}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(
+            expand_braces("*.{sql,dump}.gz"),
+            vec!["*.sql.gz", "*.dump.gz"]
+        );
+        assert_eq!(
+            expand_braces("{db,logs}-*.{tar,zip}"),
+            vec!["db-*.tar", "db-*.zip", "logs-*.tar", "logs-*.zip"]
+        );
+        assert_eq!(
+            expand_braces("*.{sql,tar.{gz,xz}}"),
+            vec!["*.sql", "*.tar.gz", "*.tar.xz"]
+        );
+        assert_eq!(expand_braces("*.{sql,sql}"), vec!["*.sql"]);
+        assert_eq!(expand_braces("{literal}*.sql"), vec!["{literal}*.sql"]);
+        assert_eq!(expand_braces("*.{sql"), vec!["*.{sql"]);
+        assert_eq!(expand_braces("*.sql"), vec!["*.sql"]);
+    }
+
+    #[test]
+    fn test_find_latest_file_with_brace_pattern() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+        let dir = dir_path.to_str().unwrap();
+
+        let sql = dir_path.join("db.sql.gz");
+        File::create(&sql)?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        let dump = dir_path.join("db.dump.gz");
+        File::create(&dump)?;
+        thread::sleep(std::time::Duration::from_millis(10));
+        File::create(dir_path.join("db.log.gz"))?;
+
+        assert_eq!(
+            FileFinder::find_latest_file_with_period(dir, &["*.{sql,dump}.gz"], None)?,
+            Some(dump.clone())
+        );
+
+        // Overlapping entries in a pattern list still yield each file once
+        let options = SearchOptions {
+            exclude_patterns: vec!["*.{dump,log}.gz".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            FileFinder::find_latest_file_with_options(
+                dir,
+                &["*.{sql,dump}.gz", "*.sql.gz"],
+                &options
+            )?,
+            Some(sql)
+        );
+        assert_eq!(
+            FileFinder::collect_candidates(
+                dir,
+                &["*.{sql,dump}.gz", "*.gz"],
+                &SearchOptions::default()
+            )?
+            .len(),
+            3
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_future_modified_time_counts_as_now() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_str().unwrap();
+        let future = temp_dir.path().join("db.sql");
+        File::create(&future)?
+            .set_modified(SystemTime::now() + std::time::Duration::from_secs(2 * 3600))?;
+
+        let tight = SearchOptions {
+            check_period: Some(Duration::minutes(1)),
+            ..Default::default()
+        };
+        let (path, modified, _) = FileFinder::find_latest_file_with_meta(dir, &["*.sql"], &tight)?
+            .expect("a future file is recent");
+        assert_eq!(path, future);
+        // The real modified time is kept for duplicate detection
+        assert!(modified > Local::now());
+
+        // Compared as now, it is not newer than a cutoff that hasn't come yet
+        let ahead = SearchOptions {
+            newer_than: Some(Local::now() + Duration::hours(1)),
+            ..Default::default()
+        };
+        assert_eq!(
+            FileFinder::find_latest_file_with_options(dir, &["*.sql"], &ahead)?,
+            None
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,165 @@
+use crate::error::{bail_config, Context, Result};
+use crate::runner::SelectedFile;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// What happens to a file once it has been sent (`after_send`).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum AfterSend {
+    /// Leave the file where it is.
+    #[default]
+    Keep,
+    Delete,
+    /// Move the file into this directory, creating it if needed.
+    Move(PathBuf),
+}
+
+impl AfterSend {
+    /// Parses `"keep"`, `"delete"` or `"move:<dir>"`.
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "keep" => Ok(Self::Keep),
+            "delete" => Ok(Self::Delete),
+            _ => match value.strip_prefix("move:") {
+                Some("") => bail_config!("'move:' needs a directory, e.g. \"move:/srv/sent\""),
+                Some(dir) => Ok(Self::Move(PathBuf::from(dir))),
+                None => {
+                    bail_config!("'{value}' is not one of \"keep\", \"delete\" or \"move:<dir>\"")
+                }
+            },
+        }
+    }
+
+    /// Deletes or moves `path`, returning where a moved file ended up.
+    pub fn apply(&self, path: &Path) -> Result<Option<PathBuf>> {
+        match self {
+            Self::Keep => Ok(None),
+            Self::Delete => {
+                fs::remove_file(path).with_context(|| format!("Failed to delete {path:?}"))?;
+                Ok(None)
+            }
+            Self::Move(dir) => {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create directory {dir:?}"))?;
+                let file_name = path.file_name().context("Failed to get file name")?;
+                let target = dir.join(file_name);
+                move_file(path, &target)
+                    .with_context(|| format!("Failed to move {path:?} to {dir:?}"))?;
+                Ok(Some(target))
+            }
+        }
+    }
+}
+
+/// Renames `from` to `to`, falling back to copy and delete when they are on
+/// different filesystems.
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+/// Runs `command` with `sh -c` after a successful upload. Details of the
+/// sent file are passed in the environment:
+///
+/// - `SENDER_BACKUP_NAME`: name of the backup
+/// - `SENDER_FILE`: path of the uploaded file
+/// - `SENDER_FILE_SIZE`: size in bytes
+/// - `SENDER_FILE_MTIME`: modified time as RFC 3339
+///
+/// The hook's output is captured and returned with its exit status.
+pub fn run_on_success(command: &str, backup_name: &str, file: &SelectedFile) -> Result<Output> {
+    Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("SENDER_BACKUP_NAME", backup_name)
+        .env("SENDER_FILE", &file.path)
+        .env("SENDER_FILE_SIZE", file.size.to_string())
+        .env("SENDER_FILE_MTIME", file.modified.to_rfc3339())
+        .output()
+        .with_context(|| format!("Failed to run on_success hook '{command}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, TimeZone};
+    use std::path::PathBuf;
+
+    fn sent_file() -> SelectedFile {
+        SelectedFile {
+            path: PathBuf::from("/backups/db.sql"),
+            modified: Local.with_ymd_and_hms(2024, 1, 15, 9, 30, 0).unwrap(),
+            size: 42,
+            version: None,
+        }
+    }
+
+    #[test]
+    fn test_run_on_success_sets_environment() -> Result<()> {
+        let output = run_on_success(
+            r#"printf '%s %s %s' "$SENDER_BACKUP_NAME" "$SENDER_FILE" "$SENDER_FILE_SIZE""#,
+            "db",
+            &sent_file(),
+        )?;
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "db /backups/db.sql 42"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_on_success_reports_exit_status() -> Result<()> {
+        let output = run_on_success("echo oops >&2; exit 3", "db", &sent_file())?;
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "oops\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_after_send_parse() -> Result<()> {
+        assert_eq!(AfterSend::parse("keep")?, AfterSend::Keep);
+        assert_eq!(AfterSend::parse("delete")?, AfterSend::Delete);
+        assert_eq!(
+            AfterSend::parse("move:/srv/sent")?,
+            AfterSend::Move(PathBuf::from("/srv/sent"))
+        );
+        assert!(AfterSend::parse("move:").is_err());
+        assert!(AfterSend::parse("archive").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_after_send_delete() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let path = temp_dir.path().join("db.sql");
+        fs::write(&path, "dump")?;
+
+        assert_eq!(AfterSend::Keep.apply(&path)?, None);
+        assert!(path.exists());
+
+        assert_eq!(AfterSend::Delete.apply(&path)?, None);
+        assert!(!path.exists());
+        assert!(AfterSend::Delete.apply(&path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_after_send_move_creates_directory() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let path = temp_dir.path().join("db.sql");
+        fs::write(&path, "dump")?;
+        let sent_dir = temp_dir.path().join("sent/2024");
+
+        let moved = AfterSend::Move(sent_dir.clone()).apply(&path)?;
+        assert_eq!(moved, Some(sent_dir.join("db.sql")));
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(sent_dir.join("db.sql"))?, "dump");
+        Ok(())
+    }
+}
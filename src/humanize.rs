@@ -0,0 +1,108 @@
+/// Formats a byte count using binary units, e.g. `40.0 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+
+    format!("{value:.1} {unit}")
+}
+
+/// Formats a duration with its two largest units, e.g. `45d 3h` or `2m 10s`.
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let secs = duration.num_seconds().max(0);
+    let parts = [
+        (secs / 86_400, "d"),
+        (secs % 86_400 / 3600, "h"),
+        (secs % 3600 / 60, "m"),
+        (secs % 60, "s"),
+    ];
+
+    let Some(first) = parts.iter().position(|(value, _)| *value > 0) else {
+        return "0s".to_string();
+    };
+    parts[first..]
+        .iter()
+        .take(2)
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{value}{unit}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Formats how long ago something happened, e.g. `3 hours ago`, using the
+/// largest whole unit. Anything under a minute, or in the future, is
+/// `just now`.
+pub fn format_relative(age: chrono::Duration) -> String {
+    let secs = age.num_seconds();
+    let (value, unit) = match secs {
+        ..=59 => return "just now".to_string(),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86_399 => (secs / 3600, "hour"),
+        _ => (secs / 86_400, "day"),
+    };
+    let plural = if value == 1 { "" } else { "s" };
+    format!("{value} {unit}{plural} ago")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1.0 KiB");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(25 * 1024 * 1024), "25.0 MiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        use chrono::Duration;
+
+        assert_eq!(format_duration(Duration::zero()), "0s");
+        assert_eq!(format_duration(Duration::seconds(42)), "42s");
+        assert_eq!(format_duration(Duration::seconds(130)), "2m 10s");
+        assert_eq!(format_duration(Duration::hours(2)), "2h");
+        assert_eq!(
+            format_duration(Duration::days(45) + Duration::hours(3) + Duration::minutes(7)),
+            "45d 3h"
+        );
+        assert_eq!(
+            format_duration(Duration::days(1) + Duration::minutes(5)),
+            "1d"
+        );
+        assert_eq!(format_duration(Duration::seconds(-5)), "0s");
+    }
+
+    #[test]
+    fn test_format_relative() {
+        use chrono::Duration;
+
+        assert_eq!(format_relative(Duration::seconds(-30)), "just now");
+        assert_eq!(format_relative(Duration::seconds(59)), "just now");
+        assert_eq!(format_relative(Duration::seconds(60)), "1 minute ago");
+        assert_eq!(format_relative(Duration::minutes(45)), "45 minutes ago");
+        assert_eq!(format_relative(Duration::hours(1)), "1 hour ago");
+        assert_eq!(
+            format_relative(Duration::hours(3) + Duration::minutes(59)),
+            "3 hours ago"
+        );
+        assert_eq!(format_relative(Duration::hours(24)), "1 day ago");
+        assert_eq!(format_relative(Duration::days(12)), "12 days ago");
+    }
+}
@@ -1,3 +1,17 @@
+pub mod bundle;
+pub mod checksum;
 pub mod config;
 pub mod discord_sender;
+pub mod error;
 pub mod file_finder;
+pub mod hooks;
+pub mod humanize;
+pub mod logging;
+pub mod metrics;
+pub mod report;
+pub mod runner;
+pub mod sender;
+pub mod slack_sender;
+pub mod state;
+pub mod telegram_sender;
+pub mod template;
@@ -0,0 +1,147 @@
+use crate::error::{Context, Result};
+use chrono::{DateTime, Local};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Logger that prints messages as-is to the console (info and below to
+/// stdout, warnings and errors to stderr) and optionally appends them with
+/// a timestamp and level to a log file.
+pub struct Logger {
+    console_level: LevelFilter,
+    file_level: LevelFilter,
+    file: Option<Mutex<File>>,
+}
+
+impl Logger {
+    /// Creates a logger. `log_file` is opened in append mode so repeated runs
+    /// accumulate in one file.
+    pub fn new(
+        console_level: LevelFilter,
+        file_level: LevelFilter,
+        log_file: Option<&Path>,
+    ) -> Result<Self> {
+        let file = log_file
+            .map(|path| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open log file {path:?}"))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            console_level,
+            file_level,
+            file: file.map(Mutex::new),
+        })
+    }
+
+    /// Installs the logger as the global `log` backend.
+    pub fn init(self) -> Result<()> {
+        let max_level = if self.file.is_some() {
+            self.console_level.max(self.file_level)
+        } else {
+            self.console_level
+        };
+        log::set_boxed_logger(Box::new(self)).context("Logger already initialized")?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.console_level
+            || (self.file.is_some() && metadata.level() <= self.file_level)
+    }
+
+    fn log(&self, record: &Record) {
+        let message = record.args().to_string();
+
+        if record.level() <= self.console_level {
+            if record.level() <= Level::Warn {
+                eprintln!("{message}");
+            } else {
+                println!("{message}");
+            }
+        }
+
+        if let Some(file) = &self.file {
+            if record.level() <= self.file_level {
+                let line = format_file_line(Local::now(), record.level(), &message);
+                let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+                // A failing log file must not abort the run
+                let _ = writeln!(file, "{line}");
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap_or_else(|e| e.into_inner()).flush();
+        }
+    }
+}
+
+/// Formats one log file line as `<ISO 8601 timestamp> <LEVEL> <message>`.
+/// Blank lines used for spacing on the console are dropped from the message.
+pub fn format_file_line(time: DateTime<Local>, level: Level, message: &str) -> String {
+    format!(
+        "{} {:<5} {}",
+        time.format("%Y-%m-%dT%H:%M:%S%.3f%:z"),
+        level,
+        message.trim_matches('\n')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_format_file_line() {
+        let time = Local.with_ymd_and_hms(2024, 1, 15, 9, 30, 5).unwrap();
+        let line = format_file_line(time, Level::Info, "\nProcessing backup: db");
+
+        let offset = time.format("%:z").to_string();
+        assert_eq!(
+            line,
+            format!("2024-01-15T09:30:05.000{offset} INFO  Processing backup: db")
+        );
+    }
+
+    #[test]
+    fn test_logger_appends_to_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("run.log");
+        std::fs::write(&path, "previous run\n")?;
+
+        let logger = Logger::new(LevelFilter::Off, LevelFilter::Info, Some(&path))?;
+        for (level, message) in [
+            (Level::Info, "sent"),
+            (Level::Debug, "hidden"),
+            (Level::Error, "failed"),
+        ] {
+            logger.log(
+                &Record::builder()
+                    .level(level)
+                    .args(format_args!("{message}"))
+                    .build(),
+            );
+        }
+
+        let contents = std::fs::read_to_string(&path)?;
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "previous run");
+        assert!(lines[1].ends_with(" INFO  sent"));
+        assert!(lines[2].ends_with(" ERROR failed"));
+        Ok(())
+    }
+}
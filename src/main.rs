@@ -1,7 +1,34 @@
 use anyhow::{Context, Result};
-use clap::Parser;
-use latest_sender::{config::Config, discord_sender::DiscordSender, file_finder::FileFinder};
-use std::path::PathBuf;
+use chrono::Local;
+use clap::{Parser, Subcommand, ValueEnum};
+use futures_util::future::join_all;
+use latest_sender::{
+    checksum::sha256_file,
+    config::{parse_duration_string, BackupConfig, Config, Target},
+    discord_sender::{mask_webhook_url, redact_webhook_urls, DiscordSender, SendOptions},
+    error::SenderError,
+    file_finder::ScanCache,
+    hooks::{run_on_success, AfterSend},
+    humanize::{format_bytes, format_relative},
+    logging::Logger,
+    metrics,
+    report::{
+        BackupReport, BackupStatus, ConnectivityReport, ConnectivityStatus, ListEntry, RunReport,
+    },
+    runner::{
+        latest_candidate, run_backup_async, run_backup_with, test_connectivity, AlreadySentCheck,
+        BackupOutcome, DeliveredCheck, RunOptions, SelectedFile, SkipReason,
+    },
+    state::{Dedup, State, StateLock},
+};
+use log::{Level, LevelFilter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::sync::Semaphore;
 
 #[derive(Parser, Debug)]
 #[clap(
@@ -15,119 +42,1255 @@ struct Args {
         long,
         value_name = "FILE",
         help = "Path to the configuration file",
-        default_value = "config.toml"
+        default_value = "config.toml",
+        global = true
     )]
     config: PathBuf,
 
+    #[clap(
+        long,
+        value_name = "DIR",
+        help = "Load every *.toml file in this directory (sorted by name) instead of --config"
+    )]
+    config_dir: Option<PathBuf>,
+
     #[clap(short, long, help = "Run in dry-run mode (don't actually send files)")]
     dry_run: bool,
 
-    #[clap(short, long, help = "Enable verbose output")]
+    #[clap(short, long, help = "Enable verbose (debug-level) output")]
     verbose: bool,
+
+    #[clap(
+        short,
+        long,
+        conflicts_with = "verbose",
+        help = "Only print warnings and errors (the JSON report is still printed)"
+    )]
+    quiet: bool,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Also append log messages with timestamps to this file"
+    )]
+    log_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Path to the file recording already-sent files",
+        default_value = "latest-sender-state.json"
+    )]
+    state_file: PathBuf,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Write Prometheus metrics for node_exporter's textfile collector to this file after each run"
+    )]
+    metrics_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Wait for another running instance to finish instead of exiting"
+    )]
+    wait_for_lock: bool,
+
+    #[clap(
+        short,
+        long,
+        help = "Send files even if they were already sent",
+        global = true
+    )]
+    force: bool,
+
+    #[clap(
+        long,
+        value_name = "NAME",
+        help = "Only run the backup with this name (can be repeated)"
+    )]
+    only: Vec<String>,
+
+    #[clap(
+        short,
+        long,
+        value_name = "N",
+        help = "Number of backups to process in parallel",
+        default_value_t = 1,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    jobs: usize,
+
+    #[clap(
+        long,
+        value_enum,
+        help = "Output format",
+        default_value_t = Format::Text
+    )]
+    format: Format,
+
+    #[clap(
+        long,
+        help = "Validate the configuration and exit without sending anything"
+    )]
+    check: bool,
+
+    #[clap(
+        long,
+        help = "Exit with status 2 if any backup had no file within its check period"
+    )]
+    fail_on_stale: bool,
+
+    #[clap(
+        long,
+        help = "Stop at the first backup that fails instead of processing the rest"
+    )]
+    stop_on_error: bool,
+
+    #[clap(
+        long,
+        help = "In watch mode, reuse a directory's previous scan while its modified time is unchanged"
+    )]
+    cache_scans: bool,
+
+    #[clap(
+        long,
+        value_name = "DURATION",
+        help = "Use this check period (e.g. 7d) for every backup, ignoring the configured ones",
+        value_parser = parse_since
+    )]
+    since: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "DURATION",
+        help = "Fail any backup whose selected file is older than this (e.g. 30d)",
+        value_parser = parse_max_file_age
+    )]
+    max_file_age: Option<chrono::Duration>,
+
+    #[clap(
+        long,
+        help = "Show upload progress on stderr (Discord only; ignored with --format json)"
+    )]
+    progress: bool,
+
+    #[clap(long, help = "Don't run on_success hooks")]
+    no_hooks: bool,
+
+    #[clap(
+        long,
+        help = "Print the file each backup would pick right now, without sending anything"
+    )]
+    list: bool,
+
+    #[clap(
+        long = "async",
+        help = "Upload with the async sender, running up to --jobs backups concurrently"
+    )]
+    use_async: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+#[derive(Subcommand, Debug, PartialEq, Eq)]
+enum Command {
+    /// Write a commented example config to the --config path (use --force to overwrite)
+    Init,
+    /// Post a short test message to each backup's webhook (or those given with --only), without reading or sending any files
+    Test,
+    /// Keep running and process the backups every --interval until SIGINT or SIGTERM
+    Watch {
+        #[clap(
+            long,
+            value_name = "DURATION",
+            help = "Time between runs (e.g. 5m)",
+            default_value = "5m",
+            value_parser = parse_interval
+        )]
+        interval: std::time::Duration,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// Human-readable progress and summary
+    Text,
+    /// A single JSON report printed at the end of the run
+    Json,
+}
+
+/// Validates `--since` up front so a typo fails before any backup runs.
+fn parse_since(value: &str) -> std::result::Result<String, String> {
+    parse_duration_string(value)
+        .map(|_| value.to_string())
+        .map_err(|e| e.to_string())
+}
+
+fn parse_max_file_age(value: &str) -> std::result::Result<chrono::Duration, String> {
+    parse_duration_string(value).map_err(|e| e.to_string())
+}
 
-    if args.verbose {
-        println!("Loading configuration from: {:?}", args.config);
+fn parse_interval(value: &str) -> std::result::Result<std::time::Duration, String> {
+    match humantime::parse_duration(value) {
+        Ok(interval) if interval.is_zero() => Err("interval must be greater than zero".to_string()),
+        Ok(interval) => Ok(interval),
+        Err(e) => Err(e.to_string()),
     }
+}
 
-    let config = Config::from_file(&args.config)
-        .with_context(|| format!("Failed to load config from {:?}", args.config))?;
+/// Filters `backups` down to the names given with `--only`, keeping config order.
+/// An empty `only` list selects every backup.
+fn select_backups<'a>(
+    backups: &'a [BackupConfig],
+    only: &[String],
+) -> Result<Vec<&'a BackupConfig>> {
+    if let Some(unknown) = only
+        .iter()
+        .find(|name| !backups.iter().any(|b| &b.name == *name))
+    {
+        anyhow::bail!("No backup named '{unknown}' in the config file");
+    }
 
-    if config.backups.is_empty() {
-        println!("No backup configurations found in the config file");
-        return Ok(());
+    Ok(backups
+        .iter()
+        .filter(|b| only.is_empty() || only.contains(&b.name))
+        .collect())
+}
+
+/// Why a run has nothing to do: no backups configured, or every one of them
+/// (or of those chosen with `--only`) disabled.
+fn no_runnable_backups(configured: usize, disabled: usize, only: &[String]) -> String {
+    let reason = if configured == 0 {
+        "the config file has no backups".to_string()
+    } else if only.is_empty() {
+        format!("all {disabled} configured backup(s) are disabled")
+    } else {
+        format!(
+            "the backup(s) selected with --only are disabled ({})",
+            only.join(", ")
+        )
+    };
+    format!("No runnable backups: {reason}")
+}
+
+/// Log messages for one backup. Buffered so that backups processed in
+/// parallel don't interleave their output.
+#[derive(Debug, Default)]
+struct Output {
+    lines: Vec<(Level, String)>,
+}
+
+static OUTPUT_LOCK: Mutex<()> = Mutex::new(());
+
+impl Output {
+    fn debug(&mut self, line: impl Into<String>) {
+        self.lines.push((Level::Debug, line.into()));
+    }
+
+    fn info(&mut self, line: impl Into<String>) {
+        self.lines.push((Level::Info, line.into()));
     }
 
-    let mut total_sent = 0;
-    let mut total_skipped = 0;
+    fn warn(&mut self, line: impl Into<String>) {
+        self.lines.push((Level::Warn, line.into()));
+    }
 
-    for backup in &config.backups {
-        println!("\nProcessing backup: {}", backup.name);
+    fn error(&mut self, line: impl Into<String>) {
+        self.lines.push((Level::Error, line.into()));
+    }
 
-        if args.verbose {
-            if let Some(ref period) = backup.check_period {
-                println!("  Check period: {period}");
-            } else {
-                println!("  Check period: none (no time filtering)");
-            }
+    fn flush(self) {
+        let _guard = OUTPUT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        for (level, line) in self.lines {
+            log::log!(level, "{line}");
         }
+    }
+}
 
-        // Parse check period from config
-        let check_period = match backup.parse_check_period() {
-            Ok(period) => period,
-            Err(e) => {
-                eprintln!(
-                    "  Error parsing check_period '{}': {e}",
-                    backup.check_period.as_ref().unwrap_or(&"none".to_string())
-                );
-                total_skipped += 1;
-                continue;
-            }
+fn process_backup(
+    backup: &BackupConfig,
+    args: &Args,
+    state: &Mutex<State>,
+    scan_cache: Option<&Arc<ScanCache>>,
+    out: &mut Output,
+) -> BackupReport {
+    let run = BackupRun::start(backup, args, state, scan_cache, out);
+    let result = run_backup_with(backup, &run.options());
+    run.finish(result, out)
+}
+
+/// Like `process_backup`, uploading with the async sender.
+async fn process_backup_async(
+    backup: &BackupConfig,
+    args: &Args,
+    state: &Mutex<State>,
+    scan_cache: Option<&Arc<ScanCache>>,
+    out: &mut Output,
+) -> BackupReport {
+    let run = BackupRun::start(backup, args, state, scan_cache, out);
+    let result = run_backup_async(backup, &run.options()).await;
+    run.finish(result, out)
+}
+
+/// Everything around one run of a backup that doesn't depend on whether it
+/// uploads with the blocking or async sender: the checks against the state
+/// file, the run options and reporting the outcome.
+struct BackupRun<'a> {
+    backup: &'a BackupConfig,
+    args: &'a Args,
+    state: &'a Mutex<State>,
+    scan_cache: Option<&'a Arc<ScanCache>>,
+    already_sent: Box<AlreadySentCheck<'a>>,
+    delivered: Box<DeliveredCheck<'a>>,
+}
+
+impl<'a> BackupRun<'a> {
+    fn start(
+        backup: &'a BackupConfig,
+        args: &'a Args,
+        state: &'a Mutex<State>,
+        scan_cache: Option<&'a Arc<ScanCache>>,
+        out: &mut Output,
+    ) -> Self {
+        start_backup(backup, out);
+        Self {
+            backup,
+            args,
+            state,
+            scan_cache,
+            already_sent: Box::new(already_sent_check(backup, args, state)),
+            delivered: Box::new(delivered_check(backup, args, state)),
+        }
+    }
+
+    fn options(&self) -> RunOptions<'_> {
+        RunOptions {
+            dry_run: self.args.dry_run,
+            already_sent: Some(&*self.already_sent),
+            delivered: Some(&*self.delivered),
+            throttled: is_throttled(self.backup, self.args, self.state),
+            max_file_age: self.args.max_file_age,
+            progress: self.args.progress && self.args.format == Format::Text,
+            scan_cache: self.scan_cache.cloned(),
+        }
+    }
+
+    /// Reports the outcome, see `report_outcome`.
+    fn finish(self, result: Result<BackupOutcome, SenderError>, out: &mut Output) -> BackupReport {
+        report_outcome(self.backup, self.args, self.state, result, out)
+    }
+}
+
+fn start_backup(backup: &BackupConfig, out: &mut Output) {
+    out.info(format!("\nProcessing backup: {}", backup.name));
+
+    if let Some(ref period) = backup.check_period {
+        out.debug(format!("  Check period: {period}"));
+    } else {
+        out.debug("  Check period: none (no time filtering)");
+    }
+}
+
+/// Runs the `on_success` hook; a failing hook is only a warning.
+fn run_hook(command: &str, backup: &BackupConfig, file: &SelectedFile, out: &mut Output) {
+    out.debug(format!("  Running on_success hook: {command}"));
+    match run_on_success(command, &backup.name, file) {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => out.warn(format!(
+            "  Warning: on_success hook exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(e) => out.warn(format!("  Warning: {e:#}")),
+    }
+}
+
+/// Deletes or moves the sent file per `after_send`; failures are only warnings.
+fn apply_after_send(backup: &BackupConfig, file: &SelectedFile, out: &mut Output) {
+    let result = backup
+        .after_send(&file.path)
+        .and_then(|action| Ok((action.apply(&file.path)?, action)));
+    match result {
+        Ok((Some(target), _)) => out.info(format!("  Moved {:?} to {target:?}", file.path)),
+        Ok((None, AfterSend::Delete)) => out.info(format!("  Deleted {:?}", file.path)),
+        Ok(_) => {}
+        Err(e) => out.warn(format!("  Warning: after_send: {e:#}")),
+    }
+}
+
+/// Skips files recorded in the state file as the backup's `dedup` mode
+/// says, unless `--force` is given.
+fn already_sent_check<'a>(
+    backup: &'a BackupConfig,
+    args: &'a Args,
+    state: &'a Mutex<State>,
+) -> impl Fn(&SelectedFile) -> bool + Sync + 'a {
+    move |file| {
+        if args.force {
+            return false;
+        }
+        let dedup = backup.dedup().unwrap_or_default();
+        // Hashed before taking the lock so parallel backups aren't serialized
+        let hash = match (&file.version, dedup) {
+            (None, Dedup::Hash) => file_hash(&file.path),
+            _ => None,
         };
+        let state = state.lock().unwrap_or_else(|e| e.into_inner());
+        match &file.version {
+            Some(version) => state.is_version_sent(&backup.name, version),
+            None => state.is_duplicate(
+                &backup.name,
+                dedup,
+                &file.path,
+                file.modified,
+                hash.as_deref(),
+            ),
+        }
+    }
+}
+
+/// Skips webhooks recorded in the state file as having accepted the file in
+/// an earlier, partially sent run, unless `--force` is given.
+fn delivered_check<'a>(
+    backup: &'a BackupConfig,
+    args: &'a Args,
+    state: &'a Mutex<State>,
+) -> impl Fn(&SelectedFile, &str) -> bool + Sync + 'a {
+    move |file, url| {
+        !args.force
+            && state
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .is_delivered(
+                    &backup.name,
+                    &file.path,
+                    file.modified,
+                    file.version.as_deref(),
+                    url,
+                )
+    }
+}
+
+/// SHA-256 of `path` for `dedup = "hash"`; a file that can't be read is
+/// never treated as a duplicate.
+fn file_hash(path: &Path) -> Option<String> {
+    sha256_file(path)
+        .inspect_err(|e| log::warn!("Warning: failed to hash {path:?}: {e:#}"))
+        .ok()
+}
+
+/// True while the backup's `once_per` interval hasn't elapsed, unless `--force` is given.
+fn is_throttled(backup: &BackupConfig, args: &Args, state: &Mutex<State>) -> bool {
+    let Ok(Some(interval)) = backup.parse_once_per() else {
+        return false;
+    };
+    !args.force
+        && state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_throttled(&backup.name, interval, Local::now())
+}
+
+/// Logs the selected file with its age and adds it to `report`.
+fn report_file(report: &mut BackupReport, file: &SelectedFile, out: &mut Output) {
+    let age = Local::now() - file.modified;
+    let relative = format_relative(age);
+    out.info(format!("  Found latest file: {:?} ({relative})", file.path));
+    report.file = Some(file.path.clone());
+    report.size = Some(file.size);
+    report.age_seconds = Some(age.num_seconds().max(0));
+    report.age = Some(relative);
+}
+
+/// Prints the outcome of a backup and records sent files in the state file.
+fn report_outcome(
+    backup: &BackupConfig,
+    args: &Args,
+    state: &Mutex<State>,
+    result: Result<BackupOutcome, SenderError>,
+    out: &mut Output,
+) -> BackupReport {
+    let mut report = BackupReport::new(&backup.name);
+    let target = backup.target.unwrap_or_default();
+    if args.dry_run {
+        report.would_send = Some(false);
+    }
+
+    match result {
+        Ok(BackupOutcome::Sent(file)) => {
+            report_file(&mut report, &file, out);
+            out.info(format!("  Sending file to {target}... ✓ Success!"));
+            report.status = BackupStatus::Sent;
+
+            let hash = match backup.dedup() {
+                Ok(Dedup::Hash) => file_hash(&file.path),
+                _ => None,
+            };
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            state.record_sent(&backup.name, &file.path, file.modified);
+            if let Some(version) = &file.version {
+                state.record_version(&backup.name, version);
+            }
+            if let Some(hash) = &hash {
+                state.record_hash(&backup.name, hash);
+            }
+            if let Err(e) = state.save(&args.state_file) {
+                out.warn(format!("  Warning: {e}"));
+            }
+            drop(state);
 
-        match FileFinder::find_latest_file_with_period(
-            &backup.source_directory,
-            &backup.file_pattern,
-            check_period,
-        ) {
-            Ok(Some(file_path)) => {
-                println!("  Found latest file: {file_path:?}");
-
-                if args.dry_run {
-                    println!("  [DRY RUN] Would send file to webhook");
-                    total_skipped += 1;
-                } else {
-                    print!("  Sending file to Discord webhook...");
-                    match DiscordSender::send_file(
-                        &backup.webhook_url,
-                        &file_path,
-                        Some(&format!("Latest backup from: {}", backup.name)),
-                    ) {
-                        Ok(_) => {
-                            println!(" ✓ Success!");
-                            total_sent += 1;
-                        }
-                        Err(e) => {
-                            println!(" ✗ Failed!");
-                            eprintln!("  Error: {e}");
-                            if args.verbose {
-                                eprintln!("  Debug: {e:?}");
-                            }
-                        }
+            if let Some(command) = backup.on_success.as_deref().filter(|_| !args.no_hooks) {
+                run_hook(command, backup, &file, out);
+            }
+            if !args.dry_run {
+                apply_after_send(backup, &file, out);
+            }
+        }
+        Ok(BackupOutcome::PartiallySent {
+            file,
+            sent,
+            total,
+            delivered,
+            error,
+        }) => {
+            // Not recorded as sent; the next run retries only the webhooks
+            // that failed
+            report_file(&mut report, &file, out);
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            state.record_delivered(
+                &backup.name,
+                &file.path,
+                file.modified,
+                file.version.as_deref(),
+                &delivered,
+            );
+            if let Err(e) = state.save(&args.state_file) {
+                out.warn(format!("  Warning: {e}"));
+            }
+            drop(state);
+
+            out.info(format!(
+                "  Sending file to {target}... ⚠ Sent to {sent} of {total} webhooks"
+            ));
+            let message = redact_webhook_urls(&format!("{error:#}"));
+            out.error(format!("  Error: {message}"));
+            report.status = BackupStatus::Partial;
+            report.error = Some(message);
+        }
+        Ok(BackupOutcome::Failed { file, error }) => {
+            report_file(&mut report, &file, out);
+            out.info(format!("  Sending file to {target}... ✗ Failed!"));
+            let message = redact_webhook_urls(&error.to_string());
+            out.error(format!("  Error: {message}"));
+            let error = anyhow::Error::from(error);
+            out.debug(redact_webhook_urls(&format!("  Debug: {error:?}")));
+            report.status = BackupStatus::Error;
+            report.error = Some(message);
+        }
+        Ok(BackupOutcome::Skipped(reason)) => {
+            report.stale = reason.is_stale();
+            report.reason = Some(reason.as_str().to_string());
+            if report.stale && backup.notify_on_missing && !args.dry_run {
+                out.warn("  Posted missing-backup alert");
+            }
+            match reason {
+                SkipReason::NoMatch if backup.cutoff_description().is_some() => out.info(format!(
+                    "  No files found matching pattern '{}' within check period",
+                    backup.file_pattern
+                )),
+                SkipReason::NoMatch => out.info(format!(
+                    "  No files found matching pattern: {}",
+                    backup.file_pattern
+                )),
+                SkipReason::Stale { latest } => out.info(format!(
+                    "  Latest file {latest:?} is older than {}, skipping",
+                    backup.cutoff_description().unwrap_or_default()
+                )),
+                SkipReason::AlreadySent(file) => {
+                    report_file(&mut report, &file, out);
+                    out.info("  Already sent this file, skipping (use --force to resend)");
+                }
+                SkipReason::Throttled(file) => {
+                    report_file(&mut report, &file, out);
+                    out.info(format!(
+                        "  Already sent within {}, skipping (throttled)",
+                        backup.once_per.as_deref().unwrap_or_default()
+                    ));
+                }
+                SkipReason::DryRun { file, plan } => {
+                    report_file(&mut report, &file, out);
+                    out.info(format!(
+                        "  [DRY RUN] Would send file to {} at {}",
+                        plan.target, plan.destination
+                    ));
+                    out.info(format!("  Size: {}", format_bytes(file.size)));
+                    out.info(format!(
+                        "  Message: {}",
+                        plan.message.replace('\n', "\n    ")
+                    ));
+                    if let Some(mention) = &plan.mention {
+                        out.info(format!("  Mention: {mention}"));
                     }
+                    report.would_send = Some(true);
+                    report.destination = Some(plan.destination);
+                    report.message = Some(plan.message);
                 }
             }
-            Ok(None) => {
-                if backup.check_period.is_some() {
-                    println!(
-                        "  No files found matching pattern '{}' within check period",
-                        backup.file_pattern
-                    );
-                } else {
-                    println!("  No files found matching pattern: {}", backup.file_pattern);
+        }
+        Err(e) => {
+            let message = match Some(e.kind()).filter(|kind| kind.is_config_error()) {
+                // Not a runtime failure: point at the config instead
+                Some(kind) => {
+                    let message = format!("backup '{}': {kind}", backup.name);
+                    out.error(format!("  Configuration error: {message}"));
+                    message
+                }
+                None => {
+                    let message = redact_webhook_urls(&format!("{e:#}"));
+                    out.error(format!("  Error: {message}"));
+                    message
                 }
-                total_skipped += 1;
+            };
+            let e = anyhow::Error::from(e);
+            out.debug(redact_webhook_urls(&format!("  Debug: {e:?}")));
+            report.status = BackupStatus::Error;
+            report.error = Some(message);
+        }
+    }
+
+    report
+}
+
+/// Processes every backup on up to `jobs` threads, flushing each backup's
+/// output as soon as it finishes. Results are returned in config order.
+///
+/// Once a result matches `stop`, no further backups are started; with
+/// several jobs, backups already running still finish. Only the backups that
+/// ran are returned.
+fn run_backups<T, F, S>(backups: &[&BackupConfig], jobs: usize, stop: S, process: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&BackupConfig, &mut Output) -> T + Sync,
+    S: Fn(&T) -> bool + Sync,
+{
+    let run_one = |backup: &BackupConfig| {
+        let mut out = Output::default();
+        let result = process(backup, &mut out);
+        out.flush();
+        result
+    };
+
+    if jobs <= 1 {
+        let mut results = Vec::new();
+        for backup in backups {
+            let result = run_one(backup);
+            let stopped = stop(&result);
+            results.push(result);
+            if stopped {
+                break;
             }
-            Err(e) => {
-                eprintln!("  Error searching for files: {e}");
-                if args.verbose {
-                    eprintln!("  Debug: {e:?}");
+        }
+        return results;
+    }
+
+    let next = AtomicUsize::new(0);
+    let stopped = AtomicBool::new(false);
+    let results = Mutex::new((0..backups.len()).map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(backups.len()) {
+            scope.spawn(|| loop {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
                 }
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(backup) = backups.get(index) else {
+                    break;
+                };
+
+                let result = run_one(backup);
+                if stop(&result) {
+                    stopped.store(true, Ordering::SeqCst);
+                }
+                results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Whether `--stop-on-error` ends the run after `report`: it failed, as
+/// opposed to being skipped.
+fn stops_run(args: &Args, report: &BackupReport) -> bool {
+    args.stop_on_error && matches!(report.status, BackupStatus::Error | BackupStatus::Partial)
+}
+
+/// Exit status when any backup failed, e.g. its upload was rejected.
+const EXIT_FAILURE: i32 = 1;
+
+/// Exit status for `--fail-on-stale`, distinct from the 1 used for errors.
+const EXIT_STALE: i32 = 2;
+
+/// The exit status for a finished run, or `None` when it succeeded. Failed
+/// backups take precedence over stale ones.
+fn exit_status(report: &RunReport, fail_on_stale: bool) -> Option<i32> {
+    if report.errors > 0 || report.partial > 0 {
+        Some(EXIT_FAILURE)
+    } else if fail_on_stale && report.stale > 0 {
+        Some(EXIT_STALE)
+    } else {
+        None
+    }
+}
+
+fn print_summary(report: &RunReport, dry_run: bool) {
+    log::info!("\n{}", "=".repeat(50));
+    log::info!("Summary:");
+    log::info!("  Total backups processed: {}", report.total);
+    log::info!("  Files sent: {}", report.sent);
+    if report.partial > 0 {
+        log::info!("  Partially sent: {}", report.partial);
+    }
+    log::info!("  Files skipped: {}", report.skipped);
+    log::info!("  Errors: {}", report.errors);
+    log::info!(
+        "  Total bytes sent: {} ({})",
+        report.bytes_sent,
+        format_bytes(report.bytes_sent)
+    );
+
+    if dry_run {
+        log::info!("\n[DRY RUN MODE] No files were actually sent");
+    }
+}
+
+/// The run summary posted to `summary_webhook`, e.g. "5 backups, 4 sent,
+/// 1 stale", followed by one line per failed backup.
+fn summary_message(report: &RunReport) -> String {
+    let plural = if report.total == 1 { "" } else { "s" };
+    let mut counts = vec![
+        format!("{} backup{plural}", report.total),
+        format!("{} sent", report.sent),
+    ];
+    let skipped = report.skipped - report.stale;
+    for (count, label) in [
+        (report.partial, "partially sent"),
+        (skipped, "skipped"),
+        (report.stale, "stale"),
+        (report.errors, "failed"),
+    ] {
+        if count > 0 {
+            counts.push(format!("{count} {label}"));
+        }
+    }
+
+    let mut message = format!("latest-sender: {}", counts.join(", "));
+    for backup in &report.backups {
+        if let Some(error) = &backup.error {
+            message.push_str(&format!("\n- {}: {error}", backup.name));
+        }
+    }
+    message
+}
+
+/// Posts the run summary to `summary_webhook`, if set; dry runs print it
+/// instead. A failed post only logs a warning.
+fn post_summary(summary_webhook: Option<&str>, report: &RunReport, dry_run: bool) {
+    let Some(url) = summary_webhook else {
+        return;
+    };
+    let message = summary_message(report);
+    if dry_run {
+        log::info!("[DRY RUN] Would post summary to {}:", mask_webhook_url(url));
+        log::info!("  {}", message.replace('\n', "\n  "));
+        return;
+    }
+    if let Err(e) = DiscordSender::send_message(url, &message, &SendOptions::default()) {
+        let error = redact_webhook_urls(&format!("{e:#}"));
+        log::warn!("Warning: failed to post summary: {error}");
+    }
+}
+
+/// Runs the `test` command for one backup.
+fn connectivity_report(backup: &BackupConfig) -> ConnectivityReport {
+    let (status, error) = match backup.target.unwrap_or_default() {
+        Target::Discord => match test_connectivity(backup) {
+            Ok(()) => (ConnectivityStatus::Ok, None),
+            Err(e) => (
+                ConnectivityStatus::Failed,
+                Some(redact_webhook_urls(&format!("{e:#}"))),
+            ),
+        },
+        _ => (ConnectivityStatus::Skipped, None),
+    };
+    ConnectivityReport {
+        name: backup.name.clone(),
+        status,
+        error,
+    }
+}
+
+fn format_connectivity(report: &ConnectivityReport) -> String {
+    match (report.status, &report.error) {
+        (ConnectivityStatus::Ok, _) => format!("{}: OK", report.name),
+        (ConnectivityStatus::Failed, error) => format!(
+            "{}: FAIL - {}",
+            report.name,
+            error.as_deref().unwrap_or("unknown error")
+        ),
+        (ConnectivityStatus::Skipped, _) => {
+            format!(
+                "{}: SKIPPED (only Discord webhooks can be tested)",
+                report.name
+            )
+        }
+    }
+}
+
+/// Looks up the file `backup` would pick for `--list`.
+fn list_entry(backup: &BackupConfig) -> ListEntry {
+    let mut entry = ListEntry::new(&backup.name, &backup.file_pattern.to_string());
+    match latest_candidate(backup) {
+        Ok(Some((file, within_period))) => {
+            entry.file = Some(file.path);
+            entry.modified = Some(file.modified);
+            entry.size = Some(file.size);
+            entry.within_period = Some(within_period);
+        }
+        Ok(None) => {}
+        Err(e) => entry.error = Some(format!("{e:#}")),
+    }
+    entry
+}
+
+/// Renders `--list` entries as an aligned text table.
+fn format_list(entries: &[ListEntry]) -> String {
+    let header = ["NAME", "PATTERN", "FILE", "MODIFIED", "SIZE", "IN PERIOD"].map(String::from);
+    let rows: Vec<[String; 6]> = entries
+        .iter()
+        .map(|entry| {
+            let file = match (&entry.file, &entry.error) {
+                (_, Some(error)) => format!("error: {error}"),
+                (Some(file), None) => file.display().to_string(),
+                (None, None) => "-".to_string(),
+            };
+            [
+                entry.name.clone(),
+                entry.pattern.clone(),
+                file,
+                entry.modified.map_or("-".to_string(), |m| {
+                    m.format("%Y-%m-%d %H:%M:%S").to_string()
+                }),
+                entry.size.map_or("-".to_string(), format_bytes),
+                match entry.within_period {
+                    Some(true) => "yes",
+                    Some(false) => "no",
+                    None => "-",
+                }
+                .to_string(),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.clone().map(|h| h.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    std::iter::once(&header)
+        .chain(&rows)
+        .map(|row| {
+            row.iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Processes every backup concurrently on a Tokio runtime, at most `jobs`
+/// at a time. Results are returned in config order.
+fn run_backups_async(
+    backups: &[&BackupConfig],
+    args: &Args,
+    state: &Mutex<State>,
+    scan_cache: Option<&Arc<ScanCache>>,
+) -> Result<Vec<BackupReport>> {
+    let runtime = tokio::runtime::Runtime::new().context("Failed to start async runtime")?;
+    let semaphore = Semaphore::new(args.jobs);
+    let stopped = AtomicBool::new(false);
+
+    let reports = runtime.block_on(join_all(backups.iter().map(|backup| async {
+        let _permit = semaphore
+            .acquire()
+            .await
+            .expect("semaphore is never closed");
+        if stopped.load(Ordering::SeqCst) {
+            return None;
+        }
+        let mut out = Output::default();
+        let report = process_backup_async(backup, args, state, scan_cache, &mut out).await;
+        out.flush();
+        if stops_run(args, &report) {
+            stopped.store(true, Ordering::SeqCst);
+        }
+        Some(report)
+    })));
+    Ok(reports.into_iter().flatten().collect())
+}
+
+/// The backups with `{name}` and `{date}` rendered in their directories and
+/// patterns, so every backup in a run sees the same date. The templates were
+/// checked when the config was loaded.
+fn resolve_placeholders(backups: &[&BackupConfig]) -> Result<Vec<BackupConfig>> {
+    let today = Local::now();
+    backups
+        .iter()
+        .map(|backup| {
+            backup
+                .with_placeholders(today)
+                .with_context(|| format!("backup '{}'", backup.name))
+        })
+        .collect()
+}
+
+/// Processes every backup once, sequentially, on threads or async.
+fn run_once(
+    backups: &[&BackupConfig],
+    args: &Args,
+    state: &Mutex<State>,
+    scan_cache: Option<&Arc<ScanCache>>,
+) -> Result<RunReport> {
+    let resolved = resolve_placeholders(backups)?;
+    let backups: Vec<&BackupConfig> = resolved.iter().collect();
+    let backups = backups.as_slice();
+    let results = if args.use_async {
+        run_backups_async(backups, args, state, scan_cache)?
+    } else {
+        run_backups(
+            backups,
+            args.jobs,
+            |report| stops_run(args, report),
+            |backup, out| process_backup(backup, args, state, scan_cache, out),
+        )
+    };
+    if results.len() < backups.len() {
+        log::error!(
+            "Stopping after a failed backup (--stop-on-error); {} backup(s) not run",
+            backups.len() - results.len()
+        );
+    }
+    Ok(RunReport {
+        dry_run: args.dry_run,
+        ..RunReport::new(results)
+    })
+}
+
+/// Runs every backup each `interval` until SIGINT or SIGTERM, printing a
+/// summary per cycle. The state file keeps files from being sent twice. A
+/// signal during a cycle lets it finish before shutting down.
+fn watch(
+    backups: &[&BackupConfig],
+    args: &Args,
+    state: &Mutex<State>,
+    interval: std::time::Duration,
+    summary_webhook: Option<&str>,
+) -> Result<()> {
+    let runtime = tokio::runtime::Runtime::new().context("Failed to start async runtime")?;
+    let mut shutdown = runtime.block_on(async { Shutdown::listen() })?;
+    let scan_cache = args.cache_scans.then(|| Arc::new(ScanCache::default()));
+    log::info!(
+        "Watching {} backup(s) every {}",
+        backups.len(),
+        humantime::format_duration(interval)
+    );
+
+    loop {
+        let report = run_once(backups, args, state, scan_cache.as_ref())?;
+        write_metrics(args, &report, state);
+        post_summary(summary_webhook, &report, args.dry_run);
+        if args.format == Format::Text {
+            print_summary(&report, args.dry_run);
+        } else {
+            println!("{}", serde_json::to_string(&report)?);
+        }
+
+        if runtime.block_on(shutdown.sleep(interval)) {
+            log::info!("Received shutdown signal, exiting");
+            return Ok(());
+        }
+    }
+}
+
+/// Writes `--metrics-file`, if given. Dry runs send nothing, so they leave
+/// the file alone. A failure only logs a warning.
+fn write_metrics(args: &Args, report: &RunReport, state: &Mutex<State>) {
+    let Some(path) = args.metrics_file.as_ref().filter(|_| !args.dry_run) else {
+        return;
+    };
+    let content = metrics::render(report, &state.lock().unwrap_or_else(|e| e.into_inner()));
+    if let Err(e) = metrics::write_atomic(path, &content) {
+        log::warn!("Warning: {e:#}");
+    }
+}
+
+/// SIGINT and SIGTERM listeners for `watch`. They are installed once, so a
+/// signal that arrives mid-cycle is still seen when the cycle ends.
+#[cfg(unix)]
+struct Shutdown {
+    interrupt: Signal,
+    terminate: Signal,
+}
+
+#[cfg(unix)]
+impl Shutdown {
+    /// Must be called inside a Tokio runtime.
+    fn listen() -> Result<Self> {
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt()).context("Failed to listen for SIGINT")?,
+            terminate: signal(SignalKind::terminate()).context("Failed to listen for SIGTERM")?,
+        })
+    }
+
+    /// Waits for `interval`, returning true as soon as a signal arrives.
+    async fn sleep(&mut self, interval: std::time::Duration) -> bool {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => false,
+            _ = self.interrupt.recv() => true,
+            _ = self.terminate.recv() => true,
+        }
+    }
+}
+
+/// Ctrl-C listener for `watch` on platforms without Unix signals.
+#[cfg(not(unix))]
+struct Shutdown;
+
+#[cfg(not(unix))]
+impl Shutdown {
+    fn listen() -> Result<Self> {
+        Ok(Self)
+    }
+
+    /// Waits for `interval`, returning true as soon as Ctrl-C is pressed.
+    async fn sleep(&mut self, interval: std::time::Duration) -> bool {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => false,
+            result = tokio::signal::ctrl_c() => match result {
+                Ok(()) => true,
+                Err(e) => {
+                    log::warn!("Failed to listen for Ctrl-C: {e}");
+                    tokio::time::sleep(interval).await;
+                    false
+                }
+            },
+        }
+    }
+}
+
+/// Loads and merges every config in `dir`. Files that fail to load are
+/// returned as errors instead so the remaining backups still run.
+fn load_config_dir(dir: &Path) -> Result<(Config, Vec<String>)> {
+    let mut config = Config {
+        summary_webhook: None,
+        backups: Vec::new(),
+    };
+    let mut errors = Vec::new();
+
+    for (path, loaded) in Config::from_dir(dir)? {
+        log::debug!("Loading configuration from: {path:?}");
+        match loaded {
+            Ok(loaded) => {
+                // The first file that sets it wins, in name order
+                config.summary_webhook = config.summary_webhook.or(loaded.summary_webhook);
+                config.backups.extend(loaded.backups);
             }
+            Err(e) => errors.push(format!("Failed to load config from {path:?}: {e:#}")),
         }
     }
 
-    println!("\n{}", "=".repeat(50));
-    println!("Summary:");
-    println!("  Total backups processed: {}", config.backups.len());
-    println!("  Files sent: {total_sent}");
-    println!("  Files skipped: {total_skipped}");
+    Ok((config, errors))
+}
 
-    if args.dry_run {
-        println!("\n[DRY RUN MODE] No files were actually sent");
+/// Fails the run once everything else is done if any config file was broken.
+fn check_load_errors(errors: &[String], source: &Path) -> Result<()> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    anyhow::bail!(
+        "{} config file(s) in {:?} failed to load",
+        errors.len(),
+        source
+    )
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let text_output = args.format == Format::Text;
+
+    let level = if args.verbose {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
+    };
+    // Keep stdout clean for the JSON report; problems still go to stderr
+    let console_level = if text_output && !args.quiet {
+        level
+    } else {
+        LevelFilter::Warn
+    };
+    Logger::new(console_level, level, args.log_file.as_deref())?.init()?;
+
+    if args.command == Some(Command::Init) {
+        Config::write_template(&args.config, args.force)?;
+        log::info!("Wrote example configuration to {:?}", args.config);
+        return Ok(());
+    }
+
+    let (mut config, load_errors) = match &args.config_dir {
+        Some(dir) => load_config_dir(dir)?,
+        None => {
+            log::debug!("Loading configuration from: {:?}", args.config);
+            let config = Config::from_file(&args.config)
+                .with_context(|| format!("Failed to load config from {:?}", args.config))?;
+            (config, Vec::new())
+        }
+    };
+    let config_source = args.config_dir.as_ref().unwrap_or(&args.config);
+
+    if let Some(since) = &args.since {
+        log::debug!("Overriding check_period with --since {since}");
+        for backup in &mut config.backups {
+            backup.check_period = Some(since.clone());
+        }
+    }
+
+    if args.check {
+        let mut problems = load_errors;
+        problems.extend(config.validate());
+        if problems.is_empty() {
+            log::info!(
+                "Configuration OK: {} backup(s) validated",
+                config.backups.len()
+            );
+            return Ok(());
+        }
+        for problem in &problems {
+            log::error!("  {problem}");
+        }
+        anyhow::bail!("Found {} problem(s) in {:?}", problems.len(), config_source);
+    }
+    for error in &load_errors {
+        log::error!("{error}");
+    }
+
+    let (backups, disabled): (Vec<_>, Vec<_>) = select_backups(&config.backups, &args.only)?
+        .into_iter()
+        .partition(|backup| backup.is_enabled());
+    for backup in &disabled {
+        log::info!("Skipping disabled backup: {}", backup.name);
+    }
+    if backups.is_empty() {
+        anyhow::bail!(no_runnable_backups(
+            config.backups.len(),
+            disabled.len(),
+            &args.only
+        ));
+    }
+
+    if args.command == Some(Command::Test) {
+        let reports: Vec<ConnectivityReport> = backups
+            .iter()
+            .map(|backup| connectivity_report(backup))
+            .collect();
+        if text_output {
+            for report in &reports {
+                log::info!("{}", format_connectivity(report));
+            }
+        } else {
+            println!("{}", serde_json::to_string_pretty(&reports)?);
+        }
+        check_load_errors(&load_errors, config_source)?;
+
+        let failed = reports
+            .iter()
+            .filter(|r| r.status == ConnectivityStatus::Failed)
+            .count();
+        if failed > 0 {
+            anyhow::bail!("{failed} webhook(s) failed the connectivity test");
+        }
+        return Ok(());
+    }
+
+    if args.list {
+        let entries: Vec<ListEntry> = resolve_placeholders(&backups)?
+            .iter()
+            .map(list_entry)
+            .collect();
+        if text_output {
+            println!("{}", format_list(&entries));
+        } else {
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+        }
+        return check_load_errors(&load_errors, config_source);
+    }
+
+    let _lock = match StateLock::acquire(&args.state_file, args.wait_for_lock) {
+        Ok(lock) => lock,
+        Err(e) if matches!(e.kind(), SenderError::AlreadyRunning(_)) => {
+            log::warn!("{e}; exiting");
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let state = Mutex::new(State::load(&args.state_file)?);
+
+    if let Some(Command::Watch { interval }) = args.command {
+        return watch(
+            &backups,
+            &args,
+            &state,
+            interval,
+            config.summary_webhook.as_deref(),
+        );
+    }
+
+    let report = run_once(&backups, &args, &state, None)?;
+    write_metrics(&args, &report, &state);
+    post_summary(config.summary_webhook.as_deref(), &report, args.dry_run);
+
+    if text_output {
+        print_summary(&report, args.dry_run);
+    } else {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+
+    check_load_errors(&load_errors, config_source)?;
+
+    match exit_status(&report, args.fail_on_stale) {
+        Some(EXIT_FAILURE) => {
+            log::error!("{} backup(s) failed", report.errors + report.partial);
+            std::process::exit(EXIT_FAILURE);
+        }
+        Some(code) => {
+            log::error!("{} backup(s) had no fresh file", report.stale);
+            std::process::exit(code);
+        }
+        None => {}
     }
 
     Ok(())
@@ -136,21 +1299,446 @@ fn main() -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
+    use latest_sender::config::Patterns;
 
     #[test]
     fn test_args_parsing() {
-        let args =
-            Args::parse_from(&["latest-sender", "-c", "test.toml", "--dry-run", "--verbose"]);
+        let args = Args::parse_from(["latest-sender", "-c", "test.toml", "--dry-run", "--verbose"]);
         assert_eq!(args.config, PathBuf::from("test.toml"));
         assert!(args.dry_run);
         assert!(args.verbose);
     }
 
+    #[test]
+    fn test_summary_message() {
+        let report = RunReport::new(vec![
+            BackupReport {
+                status: BackupStatus::Sent,
+                ..BackupReport::new("db")
+            },
+            BackupReport {
+                status: BackupStatus::Sent,
+                ..BackupReport::new("logs")
+            },
+            BackupReport {
+                stale: true,
+                reason: Some("stale".to_string()),
+                ..BackupReport::new("reports")
+            },
+            BackupReport {
+                status: BackupStatus::Error,
+                error: Some("Discord API returned error: 413".to_string()),
+                ..BackupReport::new("media")
+            },
+            BackupReport {
+                reason: Some("already_sent".to_string()),
+                ..BackupReport::new("config")
+            },
+        ]);
+        assert_eq!(
+            summary_message(&report),
+            "latest-sender: 5 backups, 2 sent, 1 skipped, 1 stale, 1 failed\n\
+             - media: Discord API returned error: 413"
+        );
+
+        let all_sent = RunReport::new(vec![BackupReport {
+            status: BackupStatus::Sent,
+            ..BackupReport::new("db")
+        }]);
+        assert_eq!(
+            summary_message(&all_sent),
+            "latest-sender: 1 backup, 1 sent"
+        );
+    }
+
+    #[test]
+    fn test_quiet_conflicts_with_verbose() {
+        let args = Args::parse_from(["latest-sender", "--quiet", "--format", "json"]);
+        assert!(args.quiet);
+        assert!(!args.verbose);
+
+        let error = Args::try_parse_from(["latest-sender", "-q", "-v"]).unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_force_and_state_file_args() {
+        let args = Args::parse_from([
+            "latest-sender",
+            "--force",
+            "--state-file",
+            "/var/lib/latest-sender/state.json",
+        ]);
+        assert!(args.force);
+        assert_eq!(
+            args.state_file,
+            PathBuf::from("/var/lib/latest-sender/state.json")
+        );
+    }
+
+    #[test]
+    fn test_init_subcommand() {
+        let args = Args::parse_from(["latest-sender", "init", "-c", "new.toml", "--force"]);
+        assert_eq!(args.command, Some(Command::Init));
+        assert_eq!(args.config, PathBuf::from("new.toml"));
+        assert!(args.force);
+    }
+
+    #[test]
+    fn test_async_args() {
+        let args = Args::parse_from(["latest-sender", "--async", "-j", "4"]);
+        assert!(args.use_async);
+        assert_eq!(args.jobs, 4);
+    }
+
+    #[test]
+    fn test_log_file_arg() {
+        let args = Args::parse_from(["latest-sender", "-v", "--log-file", "run.log"]);
+        assert!(args.verbose);
+        assert_eq!(args.log_file, Some(PathBuf::from("run.log")));
+    }
+
+    #[test]
+    fn test_only_args_repeatable() {
+        let args = Args::parse_from(["latest-sender", "--only", "db", "--only", "logs"]);
+        assert_eq!(args.only, vec!["db".to_string(), "logs".to_string()]);
+    }
+
+    #[test]
+    fn test_select_backups() -> Result<()> {
+        let backups: Vec<BackupConfig> = ["db", "logs", "reports"]
+            .iter()
+            .map(|name| BackupConfig {
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect();
+
+        let all = select_backups(&backups, &[])?;
+        assert_eq!(all.len(), 3);
+
+        let selected = select_backups(&backups, &["reports".to_string(), "db".to_string()])?;
+        let names: Vec<&str> = selected.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["db", "reports"]);
+
+        let error = select_backups(&backups, &["missing".to_string()]).unwrap_err();
+        assert!(error.to_string().contains("No backup named 'missing'"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_runnable_backups() {
+        assert_eq!(
+            no_runnable_backups(0, 0, &[]),
+            "No runnable backups: the config file has no backups"
+        );
+        assert_eq!(
+            no_runnable_backups(3, 3, &[]),
+            "No runnable backups: all 3 configured backup(s) are disabled"
+        );
+        assert_eq!(
+            no_runnable_backups(3, 1, &["db".to_string()]),
+            "No runnable backups: the backup(s) selected with --only are disabled (db)"
+        );
+    }
+
+    #[test]
+    fn test_load_config_dir() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        for name in ["web", "db"] {
+            std::fs::write(
+                temp_dir.path().join(format!("{name}.toml")),
+                format!(
+                    "[[backups]]\nname = \"{name}\"\nsource_directory = \"/srv\"\nfile_pattern = \"*\"\nwebhook_url = \"https://discord.com/api/webhooks/1/token\"\n"
+                ),
+            )?;
+        }
+        std::fs::write(temp_dir.path().join("broken.toml"), "backups = 1")?;
+
+        let (config, errors) = load_config_dir(temp_dir.path())?;
+        let names: Vec<&str> = config.backups.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["db", "web"]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("broken.toml"), "{}", errors[0]);
+        assert!(check_load_errors(&errors, temp_dir.path()).is_err());
+        assert!(check_load_errors(&[], temp_dir.path()).is_ok());
+
+        let args = Args::parse_from(["latest-sender", "--config-dir", "/etc/latest-sender"]);
+        assert_eq!(args.config_dir, Some(PathBuf::from("/etc/latest-sender")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dry_run_json_plan() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        std::fs::write(temp_dir.path().join("db.sql"), "dump")?;
+        let backups: Vec<BackupConfig> = ["db", "logs"]
+            .iter()
+            .map(|name| BackupConfig {
+                name: name.to_string(),
+                source_directory: temp_dir.path().to_str().unwrap().into(),
+                file_pattern: Patterns::Single(format!("{name}.sql")),
+                webhook_url: "https://discord.com/api/webhooks/1/secret-token".into(),
+                message_template: Some("Backup {filename}".to_string()),
+                ..Default::default()
+            })
+            .collect();
+        let backups: Vec<&BackupConfig> = backups.iter().collect();
+        let state_file = temp_dir.path().join("state.json");
+        let args = Args::parse_from([
+            "latest-sender",
+            "--dry-run",
+            "--format",
+            "json",
+            "--state-file",
+            state_file.to_str().unwrap(),
+        ]);
+
+        let report = run_once(&backups, &args, &Mutex::new(State::default()), None)?;
+        let json = serde_json::to_value(&report)?;
+        assert_eq!(json["dry_run"], true);
+        let db = &json["backups"][0];
+        assert_eq!(db["would_send"], true);
+        assert_eq!(db["reason"], "dry_run");
+        assert_eq!(db["message"], "Backup db.sql");
+        let destination = db["destination"].as_str().unwrap();
+        assert!(
+            destination.starts_with("https://discord.com/"),
+            "{destination}"
+        );
+        assert!(!destination.contains("secret-token"), "{destination}");
+        let logs = &json["backups"][1];
+        assert_eq!(logs["would_send"], false);
+        assert_eq!(logs["reason"], "no_match");
+        assert!(logs["destination"].is_null());
+        assert!(!state_file.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jobs_args() {
+        let args = Args::parse_from(["latest-sender", "--jobs", "4"]);
+        assert_eq!(args.jobs, 4);
+
+        assert!(Args::try_parse_from(["latest-sender", "--jobs", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_run_backups_parallel_keeps_order() {
+        let backups: Vec<BackupConfig> = (0..8)
+            .map(|i| BackupConfig {
+                name: i.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let refs: Vec<&BackupConfig> = backups.iter().collect();
+
+        let names = run_backups(&refs, 3, |_| false, |backup, _out| backup.name.clone());
+
+        let expected: Vec<String> = (0..8).map(|i| i.to_string()).collect();
+        assert_eq!(names, expected);
+        assert_eq!(run_backups(&refs, 1, |_| false, |_, _| ()).len(), 8);
+    }
+
+    #[test]
+    fn test_run_backups_stops_after_failure() {
+        let backups: Vec<BackupConfig> = (0..8)
+            .map(|i| BackupConfig {
+                name: i.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let refs: Vec<&BackupConfig> = backups.iter().collect();
+        let fails = |name: &String| name == "2";
+
+        let names = run_backups(&refs, 1, fails, |backup, _out| backup.name.clone());
+        assert_eq!(names, ["0", "1", "2"]);
+
+        // Backups already running finish; nothing after them starts
+        let names = run_backups(&refs, 2, fails, |backup, _out| {
+            if backup.name != "2" {
+                thread::sleep(std::time::Duration::from_millis(50));
+            }
+            backup.name.clone()
+        });
+        assert!(names.len() < 8, "{names:?}");
+        assert!(names.contains(&"2".to_string()));
+        assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let args = Args::parse_from(["latest-sender", "--stop-on-error"]);
+        let report = |status| BackupReport {
+            status,
+            ..BackupReport::new("db")
+        };
+        assert!(stops_run(&args, &report(BackupStatus::Error)));
+        assert!(stops_run(&args, &report(BackupStatus::Partial)));
+        assert!(!stops_run(&args, &report(BackupStatus::Skipped)));
+        assert!(!stops_run(
+            &Args::parse_from(["latest-sender"]),
+            &report(BackupStatus::Error)
+        ));
+    }
+
+    #[test]
+    fn test_max_file_age_arg() {
+        let args = Args::parse_from(["latest-sender", "--max-file-age", "30d"]);
+        assert_eq!(args.max_file_age, Some(chrono::Duration::days(30)));
+        assert_eq!(Args::parse_from(["latest-sender"]).max_file_age, None);
+        assert!(Args::try_parse_from(["latest-sender", "--max-file-age", "old"]).is_err());
+    }
+
+    #[test]
+    fn test_since_arg() {
+        let args = Args::parse_from(["latest-sender", "--since", "7d"]);
+        assert_eq!(args.since.as_deref(), Some("7d"));
+        assert_eq!(Args::parse_from(["latest-sender"]).since, None);
+
+        assert!(Args::try_parse_from(["latest-sender", "--since", "a week"]).is_err());
+    }
+
+    #[test]
+    fn test_no_hooks_arg() {
+        assert!(Args::parse_from(["latest-sender", "--no-hooks"]).no_hooks);
+        assert!(!Args::parse_from(["latest-sender"]).no_hooks);
+    }
+
+    #[test]
+    fn test_exit_status() {
+        let report_with = |status, stale| {
+            RunReport::new(vec![
+                BackupReport {
+                    status: BackupStatus::Sent,
+                    ..BackupReport::new("db")
+                },
+                BackupReport {
+                    status,
+                    stale,
+                    ..BackupReport::new("logs")
+                },
+            ])
+        };
+
+        let ok = report_with(BackupStatus::Sent, false);
+        assert_eq!(exit_status(&ok, true), None);
+
+        let failed = report_with(BackupStatus::Error, false);
+        assert_eq!(exit_status(&failed, false), Some(EXIT_FAILURE));
+
+        let stale = report_with(BackupStatus::Skipped, true);
+        assert_eq!(exit_status(&stale, false), None);
+        assert_eq!(exit_status(&stale, true), Some(EXIT_STALE));
+    }
+
+    #[test]
+    fn test_watch_args() {
+        assert_eq!(
+            Args::parse_from(["latest-sender", "watch"]).command,
+            Some(Command::Watch {
+                interval: std::time::Duration::from_secs(300)
+            })
+        );
+        assert_eq!(
+            Args::parse_from([
+                "latest-sender",
+                "-c",
+                "prod.toml",
+                "watch",
+                "--interval",
+                "30s"
+            ])
+            .command,
+            Some(Command::Watch {
+                interval: std::time::Duration::from_secs(30)
+            })
+        );
+        assert!(Args::try_parse_from(["latest-sender", "watch", "--interval", "0s"]).is_err());
+        assert!(Args::try_parse_from(["latest-sender", "watch", "--interval", "soon"]).is_err());
+    }
+
+    #[test]
+    fn test_shutdown_sleep_times_out() -> Result<()> {
+        let runtime = tokio::runtime::Runtime::new()?;
+        let mut shutdown = runtime.block_on(async { Shutdown::listen() })?;
+        assert!(!runtime.block_on(shutdown.sleep(std::time::Duration::from_millis(10))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_connectivity() {
+        assert_eq!(
+            Args::parse_from(["latest-sender", "--only", "db", "test"]).command,
+            Some(Command::Test)
+        );
+
+        let report = |status, error: Option<&str>| ConnectivityReport {
+            name: "db".to_string(),
+            status,
+            error: error.map(str::to_string),
+        };
+        assert_eq!(
+            format_connectivity(&report(ConnectivityStatus::Ok, None)),
+            "db: OK"
+        );
+        assert_eq!(
+            format_connectivity(&report(
+                ConnectivityStatus::Failed,
+                Some("Discord API returned error: 404 Not Found")
+            )),
+            "db: FAIL - Discord API returned error: 404 Not Found"
+        );
+        assert_eq!(
+            format_connectivity(&report(ConnectivityStatus::Skipped, None)),
+            "db: SKIPPED (only Discord webhooks can be tested)"
+        );
+    }
+
+    #[test]
+    fn test_format_list() {
+        let found = ListEntry {
+            file: Some(PathBuf::from("/srv/db.sql")),
+            modified: Some(Local.with_ymd_and_hms(2024, 1, 15, 9, 30, 0).unwrap()),
+            size: Some(2048),
+            within_period: Some(true),
+            ..ListEntry::new("db", "*.sql")
+        };
+        let missing = ListEntry::new("logs", "*.log");
+
+        assert_eq!(
+            format_list(&[found, missing]),
+            "NAME  PATTERN  FILE         MODIFIED             SIZE     IN PERIOD\n\
+             db    *.sql    /srv/db.sql  2024-01-15 09:30:00  2.0 KiB  yes\n\
+             logs  *.log    -            -                    -        -"
+        );
+        assert!(Args::parse_from(["latest-sender", "--list"]).list);
+    }
+
+    #[test]
+    fn test_format_args() {
+        let args = Args::parse_from(["latest-sender", "--format", "json"]);
+        assert_eq!(args.format, Format::Json);
+
+        assert!(Args::try_parse_from(["latest-sender", "--format", "xml"]).is_err());
+    }
+
     #[test]
     fn test_default_args() {
-        let args = Args::parse_from(&["latest-sender"]);
+        let args = Args::parse_from(["latest-sender"]);
         assert_eq!(args.config, PathBuf::from("config.toml"));
         assert!(!args.dry_run);
         assert!(!args.verbose);
+        assert_eq!(args.log_file, None);
+        assert_eq!(args.state_file, PathBuf::from("latest-sender-state.json"));
+        assert!(!args.force);
+        assert!(args.only.is_empty());
+        assert_eq!(args.jobs, 1);
+        assert_eq!(args.format, Format::Text);
+        assert!(!args.check);
+        assert_eq!(args.command, None);
+        assert!(!args.fail_on_stale);
+        assert!(!args.use_async);
     }
 }
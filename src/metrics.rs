@@ -0,0 +1,151 @@
+use crate::error::{Context, Result};
+use crate::report::{BackupStatus, RunReport};
+use crate::state::State;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// Renders the run as Prometheus text exposition format, for node_exporter's
+/// textfile collector. The last send time comes from the state file, so it
+/// is reported even for backups skipped in this run.
+pub fn render(report: &RunReport, state: &State) -> String {
+    let mut out = String::new();
+
+    metric_header(
+        &mut out,
+        "latest_sender_last_send_timestamp",
+        "Unix time of the last successful upload.",
+    );
+    for backup in &report.backups {
+        if let Some(sent_at) = state
+            .backups
+            .get(&backup.name)
+            .and_then(|record| record.sent_at)
+        {
+            let _ = writeln!(
+                out,
+                "latest_sender_last_send_timestamp{{backup=\"{}\"}} {}",
+                escape_label(&backup.name),
+                sent_at.timestamp()
+            );
+        }
+    }
+
+    metric_header(
+        &mut out,
+        "latest_sender_file_age_seconds",
+        "Age of the selected file when the run found it.",
+    );
+    for backup in &report.backups {
+        if let Some(age) = backup.age_seconds {
+            let _ = writeln!(
+                out,
+                "latest_sender_file_age_seconds{{backup=\"{}\"}} {age}",
+                escape_label(&backup.name)
+            );
+        }
+    }
+
+    metric_header(
+        &mut out,
+        "latest_sender_send_success",
+        "1 if the backup was sent or skipped without error, 0 if it failed.",
+    );
+    for backup in &report.backups {
+        let success = !matches!(backup.status, BackupStatus::Error | BackupStatus::Partial);
+        let _ = writeln!(
+            out,
+            "latest_sender_send_success{{backup=\"{}\"}} {}",
+            escape_label(&backup.name),
+            u8::from(success)
+        );
+    }
+
+    out
+}
+
+/// Writes `content` to `path` through a temporary file in the same
+/// directory, so the collector never reads a half-written file.
+pub fn write_atomic<P: AsRef<Path>>(path: P, content: &str) -> Result<()> {
+    let path = path.as_ref();
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+
+    fs::write(&temp, content).with_context(|| format!("Failed to write metrics file: {temp:?}"))?;
+    fs::rename(&temp, path)
+        .with_context(|| format!("Failed to move metrics file into place: {path:?}"))?;
+    Ok(())
+}
+
+fn metric_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} gauge");
+}
+
+/// Escapes a label value as the exposition format requires.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::BackupReport;
+    use chrono::{Local, TimeZone};
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_render() {
+        let sent_at = Local.timestamp_opt(1_700_000_000, 0).unwrap();
+        let mut state = State::default();
+        state.record_sent("prod", Path::new("/backups/prod.sql"), sent_at);
+        state.backups.get_mut("prod").unwrap().sent_at = Some(sent_at);
+
+        let report = RunReport::new(vec![
+            BackupReport {
+                file: Some(PathBuf::from("/backups/prod.sql")),
+                age_seconds: Some(3600),
+                status: BackupStatus::Sent,
+                ..BackupReport::new("prod")
+            },
+            BackupReport {
+                status: BackupStatus::Error,
+                ..BackupReport::new("logs \"eu\"")
+            },
+        ]);
+
+        assert_eq!(
+            render(&report, &state),
+            "\
+# HELP latest_sender_last_send_timestamp Unix time of the last successful upload.
+# TYPE latest_sender_last_send_timestamp gauge
+latest_sender_last_send_timestamp{backup=\"prod\"} 1700000000
+# HELP latest_sender_file_age_seconds Age of the selected file when the run found it.
+# TYPE latest_sender_file_age_seconds gauge
+latest_sender_file_age_seconds{backup=\"prod\"} 3600
+# HELP latest_sender_send_success 1 if the backup was sent or skipped without error, 0 if it failed.
+# TYPE latest_sender_send_success gauge
+latest_sender_send_success{backup=\"prod\"} 1
+latest_sender_send_success{backup=\"logs \\\"eu\\\"\"} 0
+"
+        );
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("latest_sender.prom");
+        fs::write(&path, "old")?;
+
+        write_atomic(&path, "new\n")?;
+
+        assert_eq!(fs::read_to_string(&path)?, "new\n");
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 1);
+
+        Ok(())
+    }
+}
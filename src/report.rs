@@ -0,0 +1,208 @@
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupStatus {
+    Sent,
+    /// Sent to some of the backup's webhooks but not all.
+    Partial,
+    Skipped,
+    Error,
+}
+
+/// Outcome of processing one backup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BackupReport {
+    pub name: String,
+    /// The selected file, if one was found.
+    pub file: Option<PathBuf>,
+    /// Size of the selected file in bytes.
+    pub size: Option<u64>,
+    /// Age of the selected file in seconds when it was found.
+    pub age_seconds: Option<i64>,
+    /// The same age for people, e.g. `3 hours ago`.
+    pub age: Option<String>,
+    pub status: BackupStatus,
+    pub error: Option<String>,
+    /// Why the backup was skipped, e.g. `throttled`.
+    pub reason: Option<String>,
+    /// No fresh file: nothing matched, or the latest file is older than
+    /// `check_period`.
+    pub stale: bool,
+    /// Dry runs only: whether the backup would have been sent. `reason`
+    /// says why not.
+    pub would_send: Option<bool>,
+    /// Dry runs only: where the file would go, with secrets masked.
+    pub destination: Option<String>,
+    /// Dry runs only: the rendered message that would accompany the file.
+    pub message: Option<String>,
+}
+
+impl BackupReport {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            file: None,
+            size: None,
+            age_seconds: None,
+            age: None,
+            status: BackupStatus::Skipped,
+            error: None,
+            reason: None,
+            stale: false,
+            would_send: None,
+            destination: None,
+            message: None,
+        }
+    }
+}
+
+/// Machine-readable summary of a whole run, printed with `--format json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RunReport {
+    /// Nothing was sent; the backups describe what would have been.
+    pub dry_run: bool,
+    pub backups: Vec<BackupReport>,
+    pub total: usize,
+    pub sent: usize,
+    pub partial: usize,
+    pub skipped: usize,
+    pub errors: usize,
+    pub stale: usize,
+    /// Total size of the files that were sent.
+    pub bytes_sent: u64,
+}
+
+impl RunReport {
+    pub fn new(backups: Vec<BackupReport>) -> Self {
+        let count = |status| backups.iter().filter(|b| b.status == status).count();
+        Self {
+            dry_run: false,
+            total: backups.len(),
+            sent: count(BackupStatus::Sent),
+            partial: count(BackupStatus::Partial),
+            skipped: count(BackupStatus::Skipped),
+            errors: count(BackupStatus::Error),
+            stale: backups.iter().filter(|b| b.stale).count(),
+            bytes_sent: backups
+                .iter()
+                .filter(|b| matches!(b.status, BackupStatus::Sent | BackupStatus::Partial))
+                .filter_map(|b| b.size)
+                .sum(),
+            backups,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectivityStatus {
+    Ok,
+    Failed,
+    /// The target has no test message, e.g. Slack.
+    Skipped,
+}
+
+/// Result of the `test` command for one backup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectivityReport {
+    pub name: String,
+    pub status: ConnectivityStatus,
+    pub error: Option<String>,
+}
+
+/// One row of `--list`: the file a backup would pick right now.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ListEntry {
+    pub name: String,
+    pub pattern: String,
+    pub file: Option<PathBuf>,
+    pub modified: Option<DateTime<Local>>,
+    pub size: Option<u64>,
+    /// Whether the file passes `check_period`/`newer_than`; `None` without a file.
+    pub within_period: Option<bool>,
+    pub error: Option<String>,
+}
+
+impl ListEntry {
+    pub fn new(name: &str, pattern: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            file: None,
+            modified: None,
+            size: None,
+            within_period: None,
+            error: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_run_report_totals_and_json() -> Result<()> {
+        let sent = BackupReport {
+            file: Some(PathBuf::from("/backups/db.sql.gz")),
+            size: Some(2048),
+            age_seconds: Some(10_800),
+            age: Some("3 hours ago".to_string()),
+            status: BackupStatus::Sent,
+            ..BackupReport::new("db")
+        };
+        let failed = BackupReport {
+            status: BackupStatus::Error,
+            size: Some(4096),
+            error: Some("Discord API returned error".to_string()),
+            ..BackupReport::new("logs")
+        };
+        let partial = BackupReport {
+            file: Some(PathBuf::from("/backups/app.tar")),
+            size: Some(1024),
+            status: BackupStatus::Partial,
+            error: Some("1 of 2 webhooks failed".to_string()),
+            ..BackupReport::new("app")
+        };
+        let skipped = BackupReport {
+            stale: true,
+            reason: Some("stale".to_string()),
+            ..BackupReport::new("reports")
+        };
+
+        let report = RunReport::new(vec![sent, failed, skipped, partial]);
+        assert_eq!(report.total, 4);
+        assert_eq!(report.sent, 1);
+        assert_eq!(report.partial, 1);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(report.errors, 1);
+        assert_eq!(report.stale, 1);
+        assert_eq!(report.bytes_sent, 3072);
+
+        let json = serde_json::to_value(&report)?;
+        assert_eq!(json["backups"][0]["name"], "db");
+        assert_eq!(json["backups"][0]["file"], "/backups/db.sql.gz");
+        assert_eq!(json["backups"][0]["status"], "sent");
+        assert_eq!(json["backups"][1]["status"], "error");
+        assert_eq!(json["backups"][1]["error"], "Discord API returned error");
+        assert!(json["backups"][2]["file"].is_null());
+        assert_eq!(json["backups"][2]["stale"], true);
+        assert_eq!(json["backups"][2]["reason"], "stale");
+        assert!(json["backups"][0]["reason"].is_null());
+        assert_eq!(json["errors"], 1);
+        assert_eq!(json["bytes_sent"], 3072);
+        assert_eq!(json["backups"][3]["status"], "partial");
+        assert_eq!(json["backups"][0]["size"], 2048);
+        assert_eq!(json["backups"][0]["age_seconds"], 10_800);
+        assert_eq!(json["backups"][0]["age"], "3 hours ago");
+        assert!(json["backups"][2]["age"].is_null());
+        assert_eq!(json["dry_run"], false);
+        assert!(json["backups"][0]["would_send"].is_null());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1619 @@
+use crate::bundle::Bundle;
+use crate::checksum::sha256_file;
+use crate::config::{BackupConfig, Target};
+use crate::discord_sender::{
+    mask_webhook_url, redact_webhook_urls, DiscordSender, EmbedField, SendOptions,
+    MAX_CONTENT_LENGTH,
+};
+use crate::error::{bail, Context, Result, SenderError};
+use crate::file_finder::{FileFinder, ScanCache, SearchOptions};
+use crate::humanize::{format_bytes, format_duration};
+use crate::sender::FileSender;
+use crate::slack_sender::{SlackSender, SLACK_FILES_UPLOAD_URL};
+use crate::telegram_sender::{TelegramSender, TELEGRAM_API_URL};
+use crate::template::{
+    render_message, render_upload_filename, MessageContext, DEFAULT_MESSAGE_TEMPLATE,
+};
+use chrono::{DateTime, Duration, Local};
+use encoding_rs::Encoding;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The file selected for a backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectedFile {
+    pub path: PathBuf,
+    pub modified: DateTime<Local>,
+    pub size: u64,
+    /// Version from the backup's `manifest_file`, or the modified time of
+    /// the newest file in a `bundle`. Used instead of the path to tell
+    /// whether the file was already sent.
+    pub version: Option<String>,
+}
+
+/// What would have been sent in a dry run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DryRunPlan {
+    pub target: Target,
+    /// Destination URL with the secret part masked.
+    pub destination: String,
+    pub message: String,
+    pub mention: Option<String>,
+}
+
+/// Why a backup was not sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// No file matched the patterns.
+    NoMatch,
+    /// The latest matching file is older than `check_period` or `newer_than`.
+    Stale { latest: PathBuf },
+    /// `RunOptions::already_sent` returned true for the file.
+    AlreadySent(SelectedFile),
+    /// A file was sent within `once_per`; see `RunOptions::throttled`.
+    Throttled(SelectedFile),
+    /// Dry run; nothing was uploaded.
+    DryRun {
+        file: SelectedFile,
+        plan: DryRunPlan,
+    },
+}
+
+impl SkipReason {
+    /// True when the backup has no fresh file at all.
+    pub fn is_stale(&self) -> bool {
+        matches!(self, SkipReason::NoMatch | SkipReason::Stale { .. })
+    }
+
+    /// Short machine-readable name, used in reports.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::NoMatch => "no_match",
+            SkipReason::Stale { .. } => "stale",
+            SkipReason::AlreadySent(_) => "already_sent",
+            SkipReason::Throttled(_) => "throttled",
+            SkipReason::DryRun { .. } => "dry_run",
+        }
+    }
+}
+
+/// Result of running one backup.
+#[derive(Debug)]
+pub enum BackupOutcome {
+    Sent(SelectedFile),
+    Skipped(SkipReason),
+    /// The upload itself failed.
+    Failed {
+        file: SelectedFile,
+        error: SenderError,
+    },
+    /// With several webhooks, some accepted the file and others failed.
+    PartiallySent {
+        file: SelectedFile,
+        sent: usize,
+        total: usize,
+        /// URLs of the webhooks that have the file, including those that
+        /// accepted it in an earlier run.
+        delivered: Vec<String>,
+        error: SenderError,
+    },
+}
+
+/// Decides whether a file (by path and modified time, or manifest version)
+/// was already sent.
+pub type AlreadySentCheck<'a> = dyn Fn(&SelectedFile) -> bool + Sync + 'a;
+
+/// Decides whether the webhook at a URL already accepted a file in an
+/// earlier, partially sent run.
+pub type DeliveredCheck<'a> = dyn Fn(&SelectedFile, &str) -> bool + Sync + 'a;
+
+/// Settings for [`run_backup_with`].
+#[derive(Default)]
+pub struct RunOptions<'a> {
+    /// Stop before uploading and report what would be sent.
+    pub dry_run: bool,
+    /// Files for which this returns true are skipped as already sent.
+    pub already_sent: Option<&'a AlreadySentCheck<'a>>,
+    /// Discord webhooks for which this returns true are not posted to again.
+    pub delivered: Option<&'a DeliveredCheck<'a>>,
+    /// Skip a new file as throttled instead of uploading it.
+    pub throttled: bool,
+    /// Fail the backup when the selected file is older than this.
+    pub max_file_age: Option<Duration>,
+    /// Print upload progress to stderr (Discord only).
+    pub progress: bool,
+    /// Glob results shared between runs, see [`ScanCache`].
+    pub scan_cache: Option<Arc<ScanCache>>,
+}
+
+/// Finds the latest file for `backup` and sends it.
+///
+/// Invalid settings and errors before the upload (searching, reading the
+/// file, rendering the message) are returned as `Err`; a failed upload is
+/// `Ok(BackupOutcome::Failed)`.
+pub fn run_backup(backup: &BackupConfig, dry_run: bool) -> Result<BackupOutcome> {
+    run_backup_with(
+        backup,
+        &RunOptions {
+            dry_run,
+            ..Default::default()
+        },
+    )
+}
+
+/// Like [`run_backup`], with control over duplicate detection.
+pub fn run_backup_with(backup: &BackupConfig, options: &RunOptions) -> Result<BackupOutcome> {
+    let upload = match prepare(backup, options)? {
+        Prepared::Done(outcome) => return Ok(outcome),
+        Prepared::Alert(alert) => {
+            let results = backup
+                .webhook_url
+                .as_slice()
+                .iter()
+                .map(|url| {
+                    let result =
+                        DiscordSender::send_message(url, &alert.content, &alert.send_options);
+                    (url.clone(), result)
+                })
+                .collect();
+            combine_results(results).context("Failed to post missing-backup alert")?;
+            return Ok(BackupOutcome::Skipped(alert.reason));
+        }
+        Prepared::Upload(upload) => upload,
+    };
+
+    let results = if let Some(content) = &upload.inline {
+        backup
+            .webhook_url
+            .as_slice()
+            .iter()
+            .filter(|url| upload.is_pending(url))
+            .map(|url| {
+                let result = DiscordSender::send_message(url, content, &upload.send_options);
+                (url.clone(), result)
+            })
+            .collect()
+    } else {
+        match build_senders(backup, &upload.send_options) {
+            Ok(senders) => senders
+                .into_iter()
+                .filter(|(url, _)| upload.is_pending(url))
+                .map(|(url, sender)| (url, upload.send_with(sender.as_ref())))
+                .collect(),
+            Err(e) => vec![(destination_url(backup).to_string(), Err(e))],
+        }
+    };
+
+    Ok(upload.finish(results))
+}
+
+/// Async version of [`run_backup_with`], uploading with
+/// [`DiscordSender::send_file_async`]. Slack and Telegram uploads run on a
+/// blocking thread.
+pub async fn run_backup_async(
+    backup: &BackupConfig,
+    options: &RunOptions<'_>,
+) -> Result<BackupOutcome> {
+    let upload = match prepare(backup, options)? {
+        Prepared::Done(outcome) => return Ok(outcome),
+        Prepared::Alert(alert) => {
+            let mut results = Vec::new();
+            for url in backup.webhook_url.as_slice() {
+                let result =
+                    DiscordSender::send_message_async(url, &alert.content, &alert.send_options)
+                        .await;
+                results.push((url.clone(), result));
+            }
+            combine_results(results).context("Failed to post missing-backup alert")?;
+            return Ok(BackupOutcome::Skipped(alert.reason));
+        }
+        Prepared::Upload(upload) => upload,
+    };
+
+    let results = match upload.target {
+        Target::Discord => {
+            let mut results = Vec::new();
+            for url in backup.webhook_url.as_slice() {
+                if !upload.is_pending(url) {
+                    continue;
+                }
+                let result = match &upload.inline {
+                    Some(content) => {
+                        DiscordSender::send_message_async(url, content, &upload.send_options).await
+                    }
+                    None => DiscordSender::send_file_async_detailed(
+                        url,
+                        &upload.file.path,
+                        Some(&upload.message),
+                        &upload.send_options,
+                    )
+                    .await
+                    .map(|result| {
+                        log::debug!("Discord upload of {:?}: {result}", upload.file.path);
+                    }),
+                };
+                results.push((url.clone(), result));
+            }
+            results
+        }
+        target => {
+            let result = match build_senders(backup, &upload.send_options) {
+                Ok(mut senders) => {
+                    let (_, sender) = senders.remove(0);
+                    let (path, message) = (upload.file.path.clone(), upload.message.clone());
+                    tokio::task::spawn_blocking(move || sender.send(&path, Some(&message)))
+                        .await
+                        .with_context(|| format!("{target} upload task failed"))
+                        .and_then(|result| result)
+                }
+                Err(e) => Err(e),
+            };
+            vec![(destination_url(backup).to_string(), result)]
+        }
+    };
+
+    Ok(upload.finish(results))
+}
+
+/// Folds per-webhook results, each labelled by its URL, into one: `Ok` only
+/// when every webhook succeeded. A single failure is returned as-is; several
+/// are listed with the redacted URL of each.
+fn combine_results(results: Vec<(String, Result<()>)>) -> Result<()> {
+    let total = results.len();
+    let mut failures: Vec<_> = results
+        .into_iter()
+        .filter_map(|(label, result)| result.err().map(|e| (label, e)))
+        .collect();
+    match failures.len() {
+        0 => Ok(()),
+        1 if total == 1 => Err(failures.remove(0).1),
+        failed => Err(SenderError::Other(format!(
+            "{failed} of {total} webhooks failed: {}",
+            failures
+                .iter()
+                .map(|(url, e)| format!("{}: {e:#}", redact_webhook_urls(url)))
+                .collect::<Vec<_>>()
+                .join("; ")
+        ))),
+    }
+}
+
+/// Everything needed to upload a backup's file.
+struct Upload {
+    file: SelectedFile,
+    message: String,
+    send_options: SendOptions,
+    target: Target,
+    /// Message content to post instead of attaching the file
+    /// (`inline_if_smaller_than`).
+    inline: Option<String>,
+    /// Keeps the `bundle` archive that `file` points at until the upload
+    /// is done.
+    _bundle: Option<Bundle>,
+    /// Webhooks that accepted the file in an earlier run and are skipped.
+    delivered: Vec<String>,
+}
+
+impl Upload {
+    fn send_with(&self, sender: &dyn FileSender) -> Result<()> {
+        sender.send(&self.file.path, Some(&self.message))
+    }
+
+    /// True unless the webhook at `url` already has the file.
+    fn is_pending(&self, url: &str) -> bool {
+        !self.delivered.iter().any(|delivered| delivered == url)
+    }
+
+    /// Turns the result of each webhook posted to (labelled by its URL) into
+    /// the outcome of the backup.
+    fn finish(self, results: Vec<(String, Result<()>)>) -> BackupOutcome {
+        let total = self.delivered.len() + results.len();
+        let mut delivered = self.delivered;
+        delivered.extend(
+            results
+                .iter()
+                .filter(|(_, result)| result.is_ok())
+                .map(|(url, _)| url.clone()),
+        );
+        match combine_results(results) {
+            Ok(()) => BackupOutcome::Sent(self.file),
+            Err(error) if delivered.is_empty() => BackupOutcome::Failed {
+                file: self.file,
+                error,
+            },
+            Err(error) => BackupOutcome::PartiallySent {
+                file: self.file,
+                sent: delivered.len(),
+                total,
+                delivered,
+                error,
+            },
+        }
+    }
+}
+
+/// A text-only alert for a backup without a fresh file (`notify_on_missing`).
+struct Alert {
+    reason: SkipReason,
+    content: String,
+    send_options: SendOptions,
+}
+
+enum Prepared {
+    Done(BackupOutcome),
+    Alert(Box<Alert>),
+    Upload(Box<Upload>),
+}
+
+/// Selects the file and builds the message, stopping early when there is
+/// nothing to upload.
+fn prepare(backup: &BackupConfig, options: &RunOptions) -> Result<Prepared> {
+    let mut search_options = backup.search_options().context("Invalid search settings")?;
+    search_options.scan_cache = options.scan_cache.clone();
+    let mut send_options = backup.send_options().context("Invalid send settings")?;
+    send_options.progress = options.progress;
+
+    let mut bundle = None;
+    let found = match &backup.manifest_file {
+        Some(manifest) => find_from_manifest(backup, manifest)?,
+        None if backup.bundle => {
+            bundle = Bundle::create(backup, &search_options)?;
+            // The archive's path differs on every run, so it is recognized as
+            // already sent by the newest bundled file instead
+            bundle.as_ref().map(|bundle| SelectedFile {
+                path: bundle.path.clone(),
+                modified: bundle.modified,
+                size: bundle.size,
+                version: Some(bundle.modified.to_rfc3339()),
+            })
+        }
+        None => {
+            let retries = backup.open_retries.unwrap_or(0);
+            let delay = backup
+                .open_retry_delay()
+                .context("Invalid open_retry_delay")?;
+            find_with_open_retries(retries, delay, || {
+                FileFinder::find_latest_file_in_dirs(
+                    backup.source_directory.as_slice(),
+                    backup.file_pattern.as_slice(),
+                    &search_options,
+                )
+                .context("Failed to search for files")
+            })?
+            .map(|(path, modified, size)| SelectedFile {
+                path,
+                modified,
+                size,
+                version: None,
+            })
+        }
+    };
+    let Some(file) = found else {
+        let reason = match backup.manifest_file {
+            Some(_) => SkipReason::NoMatch,
+            None => stale_reason(backup, search_options),
+        };
+        if backup.notify_on_missing && !options.dry_run {
+            return Ok(Prepared::Alert(Box::new(Alert {
+                content: missing_message(backup, &reason),
+                reason,
+                send_options,
+            })));
+        }
+        return Ok(Prepared::Done(BackupOutcome::Skipped(reason)));
+    };
+
+    if let Some(max_age) = options.max_file_age {
+        let age = Local::now() - file.modified;
+        if age > max_age {
+            bail!(
+                "Latest file {:?} is {} old, older than --max-file-age {}",
+                file.path,
+                format_duration(age),
+                format_duration(max_age)
+            );
+        }
+    }
+
+    if options
+        .already_sent
+        .is_some_and(|already_sent| already_sent(&file))
+    {
+        return Ok(Prepared::Done(BackupOutcome::Skipped(
+            SkipReason::AlreadySent(file),
+        )));
+    }
+    if options.throttled {
+        return Ok(Prepared::Done(BackupOutcome::Skipped(
+            SkipReason::Throttled(file),
+        )));
+    }
+
+    let context = MessageContext {
+        name: &backup.name,
+        filename: &file
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        size: file.size,
+        mtime: file.modified,
+    };
+    let template = backup
+        .message_template
+        .as_deref()
+        .unwrap_or(DEFAULT_MESSAGE_TEMPLATE);
+    let mut message =
+        render_message(template, &context).context("Failed to render message_template")?;
+
+    if backup.include_checksum {
+        let digest = sha256_file(&file.path).context("Failed to compute checksum")?;
+        message.push_str(&format!("\nsha256: {digest}"));
+    }
+
+    if let Some(embed) = &mut send_options.embed {
+        embed.fields = embed_fields(&context);
+    }
+    send_options.mention = backup.mention_for(file.size).map(str::to_string);
+    if let Some(template) = &backup.upload_filename {
+        send_options.upload_filename = Some(
+            render_upload_filename(template, file.modified)
+                .context("Failed to render upload_filename")?,
+        );
+    }
+
+    let target = backup.target.unwrap_or_default();
+
+    if bundle.is_some() && target == Target::Discord && file.size > send_options.max_file_size {
+        return Err(SenderError::FileTooLarge {
+            service: "Discord",
+            path: file.path,
+            size: file.size,
+            limit: send_options.max_file_size,
+        })
+        .context("Bundle is too large to upload");
+    }
+
+    if options.dry_run {
+        let plan = DryRunPlan {
+            target,
+            destination: match target {
+                Target::Discord => backup
+                    .webhook_url
+                    .as_slice()
+                    .iter()
+                    .map(|url| mask_webhook_url(url))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                _ => mask_webhook_url(destination_url(backup)),
+            },
+            message,
+            mention: send_options.mention,
+        };
+        return Ok(Prepared::Done(BackupOutcome::Skipped(SkipReason::DryRun {
+            file,
+            plan,
+        })));
+    }
+
+    let inline = match backup.inline_if_smaller_than {
+        Some(threshold) if target == Target::Discord => inline_content(
+            &file,
+            threshold,
+            &message,
+            send_options.mention.as_deref(),
+            backup.encoding().context("Invalid encoding")?,
+        )
+        .context("Failed to read file for inline posting")?,
+        _ => None,
+    };
+
+    let delivered = match options.delivered {
+        Some(delivered) if target == Target::Discord => backup
+            .webhook_url
+            .as_slice()
+            .iter()
+            .filter(|url| delivered(&file, url))
+            .cloned()
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    Ok(Prepared::Upload(Box::new(Upload {
+        file,
+        message,
+        send_options,
+        target,
+        inline,
+        _bundle: bundle,
+        delivered,
+    })))
+}
+
+/// The message content that posts `file` inline: `message` followed by the
+/// file's text in a code block, after `mention` if any. `None` when the file
+/// should be attached instead: it is not under `threshold` bytes, is not
+/// valid text in `encoding` (or the encoding named by its byte order mark),
+/// or the content would not fit in one Discord message.
+pub fn inline_content(
+    file: &SelectedFile,
+    threshold: u64,
+    message: &str,
+    mention: Option<&str>,
+    encoding: &'static Encoding,
+) -> Result<Option<String>> {
+    if file.size >= threshold {
+        return Ok(None);
+    }
+    let bytes = fs::read(&file.path)?;
+    let (encoding, bom_length) = Encoding::for_bom(&bytes).unwrap_or((encoding, 0));
+    let text =
+        match encoding.decode_without_bom_handling_and_without_replacement(&bytes[bom_length..]) {
+            Some(text) if !text.contains('\0') && !text.contains("```") => text,
+            _ => return Ok(None),
+        };
+    let mut content = match mention {
+        Some(mention) => format!("{mention} {message}"),
+        None => message.to_string(),
+    };
+    content.push_str(&format!("\n```\n{}\n```", text.trim_end_matches('\n')));
+    Ok((content.chars().count() <= MAX_CONTENT_LENGTH).then_some(content))
+}
+
+/// The file named in the backup's `manifest_file`, tagged with its version.
+/// With several source directories, the first one holding the manifest is used.
+fn find_from_manifest(backup: &BackupConfig, manifest: &str) -> Result<Option<SelectedFile>> {
+    let mut entry = None;
+    for directory in backup.source_directory.as_slice() {
+        entry = FileFinder::find_from_manifest(directory, manifest)
+            .context("Failed to read manifest_file")?;
+        if entry.is_some() {
+            break;
+        }
+    }
+    Ok(entry.map(|entry| SelectedFile {
+        path: entry.path,
+        modified: entry.modified,
+        size: entry.size,
+        version: Some(entry.version),
+    }))
+}
+
+/// Runs `scan`, and runs it again up to `retries` times, `delay` apart, while
+/// the file it selects is gone by the time it is opened.
+fn find_with_open_retries(
+    retries: u32,
+    delay: std::time::Duration,
+    mut scan: impl FnMut() -> Result<Option<(PathBuf, DateTime<Local>, u64)>>,
+) -> Result<Option<(PathBuf, DateTime<Local>, u64)>> {
+    let mut attempt = 0;
+    loop {
+        let found = scan()?;
+        let Some((path, ..)) = found.as_ref().filter(|_| attempt < retries) else {
+            return Ok(found);
+        };
+        match fs::File::open(path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                attempt += 1;
+                log::debug!(
+                    "{path:?} disappeared before it could be opened; re-scanning in {delay:?} ({attempt}/{retries})"
+                );
+                std::thread::sleep(delay);
+            }
+            _ => return Ok(found),
+        }
+    }
+}
+
+/// Tells a file older than the cutoff apart from no match at all.
+fn stale_reason(backup: &BackupConfig, search_options: SearchOptions) -> SkipReason {
+    if search_options.check_period.is_none() && search_options.newer_than.is_none() {
+        return SkipReason::NoMatch;
+    }
+
+    let unfiltered = SearchOptions {
+        check_period: None,
+        newer_than: None,
+        ..search_options
+    };
+    match FileFinder::find_latest_file_in_dirs(
+        backup.source_directory.as_slice(),
+        backup.file_pattern.as_slice(),
+        &unfiltered,
+    ) {
+        Ok(Some((latest, ..))) => SkipReason::Stale { latest },
+        _ => SkipReason::NoMatch,
+    }
+}
+
+/// Alert text posted for a backup without a fresh file.
+pub fn missing_message(backup: &BackupConfig, reason: &SkipReason) -> String {
+    let mut message = format!("⚠️ No recent backup found for {}", backup.name);
+    if let (SkipReason::Stale { latest }, Some(cutoff)) = (reason, backup.cutoff_description()) {
+        let name = latest.file_name().unwrap_or_default().to_string_lossy();
+        message.push_str(&format!(" (latest file {name} is older than {cutoff})"));
+    }
+    message
+}
+
+/// File details shown as fields when posting an embed.
+fn embed_fields(context: &MessageContext) -> Vec<EmbedField> {
+    [
+        ("File", context.filename.to_string()),
+        ("Size", format_bytes(context.size)),
+        (
+            "Modified",
+            context.mtime.format("%Y-%m-%d %H:%M:%S").to_string(),
+        ),
+    ]
+    .into_iter()
+    .map(|(name, value)| EmbedField {
+        name: name.to_string(),
+        value,
+        inline: true,
+    })
+    .collect()
+}
+
+/// The newest matching file regardless of `check_period` and `newer_than`,
+/// and whether it passes them. Nothing is read or sent.
+pub fn latest_candidate(backup: &BackupConfig) -> Result<Option<(SelectedFile, bool)>> {
+    let search_options = backup.search_options().context("Invalid search settings")?;
+    let now = Local::now();
+    let cutoff = search_options.cutoff(now);
+    let unfiltered = SearchOptions {
+        check_period: None,
+        newer_than: None,
+        ..search_options
+    };
+
+    let found = match &backup.manifest_file {
+        Some(manifest) => find_from_manifest(backup, manifest)?,
+        None => FileFinder::find_latest_file_in_dirs(
+            backup.source_directory.as_slice(),
+            backup.file_pattern.as_slice(),
+            &unfiltered,
+        )
+        .context("Failed to search for files")?
+        .map(|(path, modified, size)| SelectedFile {
+            path,
+            modified,
+            size,
+            version: None,
+        }),
+    };
+    Ok(found.map(|file| {
+        let within_period = cutoff.is_none_or(|cutoff| file.modified.min(now) >= cutoff);
+        (file, within_period)
+    }))
+}
+
+/// Posts "latest-sender connectivity test for <name>" to the backup's
+/// webhook to check that it accepts messages. No files are read. Only
+/// Discord webhooks can be tested.
+pub fn test_connectivity(backup: &BackupConfig) -> Result<()> {
+    let target = backup.target.unwrap_or_default();
+    if target != Target::Discord {
+        bail!("connectivity test is not supported for {target}");
+    }
+    let mut send_options = backup.send_options().context("Invalid send settings")?;
+    send_options.embed = None;
+
+    let content = format!("latest-sender connectivity test for {}", backup.name);
+    let results = backup
+        .webhook_url
+        .as_slice()
+        .iter()
+        .map(|url| {
+            let result = DiscordSender::send_message(url, &content, &send_options);
+            (url.clone(), result)
+        })
+        .collect();
+    combine_results(results)
+}
+
+/// URL the backup's file is uploaded to; the first one when a Discord
+/// backup has several webhooks.
+pub fn destination_url(backup: &BackupConfig) -> &str {
+    match backup.target.unwrap_or_default() {
+        Target::Slack if backup.webhook_url.is_empty() => SLACK_FILES_UPLOAD_URL,
+        Target::Telegram if backup.webhook_url.is_empty() => TELEGRAM_API_URL,
+        _ => backup.webhook_url.first(),
+    }
+}
+
+/// Builds the senders for the backup's target, one per Discord webhook,
+/// each labelled with its URL.
+pub fn build_senders(
+    backup: &BackupConfig,
+    send_options: &SendOptions,
+) -> Result<Vec<(String, Box<dyn FileSender>)>> {
+    if backup.target.unwrap_or_default() == Target::Discord {
+        return Ok(backup
+            .webhook_url
+            .as_slice()
+            .iter()
+            .map(|url| {
+                let sender: Box<dyn FileSender> =
+                    Box::new(DiscordSender::new(url, send_options.clone()));
+                (url.clone(), sender)
+            })
+            .collect());
+    }
+    let sender = build_sender(backup, send_options)?;
+    Ok(vec![(destination_url(backup).to_string(), sender)])
+}
+
+/// Builds the sender for the backup's target, using the first webhook for
+/// Discord.
+pub fn build_sender(
+    backup: &BackupConfig,
+    send_options: &SendOptions,
+) -> Result<Box<dyn FileSender>> {
+    Ok(match backup.target.unwrap_or_default() {
+        Target::Discord => Box::new(DiscordSender::new(
+            destination_url(backup),
+            send_options.clone(),
+        )),
+        Target::Slack => {
+            let token = backup
+                .slack_token
+                .as_deref()
+                .context("slack_token is required for target \"slack\"")?;
+            let channel = backup
+                .slack_channel
+                .as_deref()
+                .context("slack_channel is required for target \"slack\"")?;
+            Box::new(SlackSender::new(destination_url(backup), token, channel))
+        }
+        Target::Telegram => {
+            let bot_token = backup
+                .bot_token
+                .as_deref()
+                .context("bot_token is required for target \"telegram\"")?;
+            let chat_id = backup
+                .chat_id
+                .as_deref()
+                .context("chat_id is required for target \"telegram\"")?;
+            Box::new(TelegramSender::new(
+                destination_url(backup),
+                bot_token,
+                chat_id,
+            ))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Patterns, WebhookUrls};
+    use crate::error::SenderError;
+    use chrono::TimeZone;
+    use encoding_rs::{UTF_16LE, UTF_8, WINDOWS_1252};
+    use mockito::Server;
+    use std::fs;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    fn backup_for(dir: &TempDir, webhook_url: String) -> BackupConfig {
+        BackupConfig {
+            name: "db".to_string(),
+            source_directory: dir.path().to_str().unwrap().into(),
+            file_pattern: Patterns::Single("*.sql".to_string()),
+            webhook_url: webhook_url.into(),
+            max_attempts: Some(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_run_backup_sends_latest_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("db.sql");
+        fs::write(&path, "dump")?;
+
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/api/webhooks/test")
+            .match_request(|req| {
+                req.utf8_lossy_body()
+                    .is_ok_and(|body| body.contains("Latest backup from: db"))
+            })
+            .with_status(204)
+            .expect(1)
+            .create();
+        let backup = backup_for(&temp_dir, format!("{}/api/webhooks/test", server.url()));
+
+        match run_backup(&backup, false)? {
+            BackupOutcome::Sent(file) => {
+                assert_eq!(file.path, path);
+                assert_eq!(file.size, 4);
+            }
+            other => panic!("expected Sent, got {other:?}"),
+        }
+        mock.assert();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_backup_sends_to_telegram() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("db.sql"), "dump")?;
+
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/bot123:abc/sendDocument")
+            .match_request(|req| {
+                req.utf8_lossy_body()
+                    .is_ok_and(|body| body.contains("Latest backup from: db"))
+            })
+            .with_status(200)
+            .with_body(r#"{"ok": true}"#)
+            .expect(1)
+            .create();
+        let backup = BackupConfig {
+            target: Some(Target::Telegram),
+            bot_token: Some("123:abc".to_string()),
+            chat_id: Some("42".to_string()),
+            ..backup_for(&temp_dir, server.url())
+        };
+
+        assert!(matches!(
+            run_backup(&backup, false)?,
+            BackupOutcome::Sent(_)
+        ));
+        mock.assert();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_backup_async_sends_latest_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("db.sql");
+        fs::write(&path, "dump")?;
+
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/webhooks/test")
+            .with_status(204)
+            .expect(1)
+            .create_async()
+            .await;
+        let backup = backup_for(&temp_dir, format!("{}/api/webhooks/test", server.url()));
+
+        match run_backup_async(&backup, &RunOptions::default()).await? {
+            BackupOutcome::Sent(file) => assert_eq!(file.path, path),
+            other => panic!("expected Sent, got {other:?}"),
+        }
+        mock.assert_async().await;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_backup_failed_upload() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("db.sql"), "dump")?;
+
+        let mut server = Server::new();
+        let _mock = server
+            .mock("POST", "/api/webhooks/test")
+            .with_status(401)
+            .create();
+        let backup = backup_for(&temp_dir, format!("{}/api/webhooks/test", server.url()));
+
+        assert!(matches!(
+            run_backup(&backup, false)?,
+            BackupOutcome::Failed { .. }
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_backup_fans_out_to_every_webhook() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("db.sql"), "dump")?;
+
+        let mut server = Server::new();
+        let team = server
+            .mock("POST", "/api/webhooks/team")
+            .with_status(204)
+            .expect(1)
+            .create();
+        let archive = server
+            .mock("POST", "/api/webhooks/archive")
+            .with_status(204)
+            .expect(1)
+            .create();
+        let mut backup = backup_for(&temp_dir, String::new());
+        backup.webhook_url = WebhookUrls::Multiple(vec![
+            format!("{}/api/webhooks/team", server.url()),
+            format!("{}/api/webhooks/archive", server.url()),
+        ]);
+
+        assert!(matches!(
+            run_backup(&backup, false)?,
+            BackupOutcome::Sent(_)
+        ));
+        team.assert();
+        archive.assert();
+        team.remove();
+        archive.remove();
+
+        // Accepts the partial run's upload, and nothing on the retry
+        let team = server
+            .mock("POST", "/api/webhooks/team")
+            .with_status(204)
+            .expect(1)
+            .create();
+        let rejected = server
+            .mock("POST", "/api/webhooks/archive")
+            .with_status(401)
+            .create();
+        let delivered = match run_backup(&backup, false)? {
+            BackupOutcome::PartiallySent {
+                sent,
+                total,
+                delivered,
+                ..
+            } => {
+                assert_eq!((sent, total), (1, 2));
+                delivered
+            }
+            other => panic!("expected PartiallySent, got {other:?}"),
+        };
+        assert_eq!(delivered, [backup.webhook_url.first().to_string()]);
+        rejected.assert();
+        rejected.remove();
+
+        let archive = server
+            .mock("POST", "/api/webhooks/archive")
+            .with_status(204)
+            .expect(1)
+            .create();
+        let check = |_: &SelectedFile, url: &str| delivered.iter().any(|d| d == url);
+        let options = RunOptions {
+            delivered: Some(&check),
+            ..Default::default()
+        };
+        assert!(matches!(
+            run_backup_with(&backup, &options)?,
+            BackupOutcome::Sent(_)
+        ));
+        team.assert();
+        archive.assert();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_backup_dry_run_plan() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("db.sql"), "dump")?;
+        let backup = BackupConfig {
+            message_template: Some("{name}: {filename}".to_string()),
+            mention: Some("@here".to_string()),
+            ..backup_for(
+                &temp_dir,
+                "https://discord.com/api/webhooks/1/token".to_string(),
+            )
+        };
+
+        match run_backup(&backup, true)? {
+            BackupOutcome::Skipped(SkipReason::DryRun { file, plan }) => {
+                assert_eq!(file.size, 4);
+                assert_eq!(plan.target, Target::Discord);
+                assert_eq!(plan.destination, "https://discord.com/***");
+                assert_eq!(plan.message, "db: db.sql");
+                assert_eq!(plan.mention.as_deref(), Some("@here"));
+            }
+            other => panic!("expected a dry run, got {other:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_backup_skip_reasons() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut backup = backup_for(&temp_dir, String::new());
+
+        let outcome = run_backup(&backup, true)?;
+        assert!(matches!(
+            outcome,
+            BackupOutcome::Skipped(SkipReason::NoMatch)
+        ));
+
+        let path = temp_dir.path().join("db.sql");
+        fs::File::create(&path)?.set_modified(SystemTime::now() - Duration::from_secs(7200))?;
+        backup.check_period = Some("1h".to_string());
+        match run_backup(&backup, true)? {
+            BackupOutcome::Skipped(reason @ SkipReason::Stale { .. }) => {
+                assert!(reason.is_stale());
+                assert_eq!(
+                    reason,
+                    SkipReason::Stale {
+                        latest: path.clone()
+                    }
+                );
+            }
+            other => panic!("expected Stale, got {other:?}"),
+        }
+
+        backup.check_period = None;
+        let already_sent = |file: &SelectedFile| file.path == path;
+        let options = RunOptions {
+            dry_run: false,
+            already_sent: Some(&already_sent),
+            ..Default::default()
+        };
+        match run_backup_with(&backup, &options)? {
+            BackupOutcome::Skipped(reason @ SkipReason::AlreadySent(_)) => {
+                assert!(!reason.is_stale());
+            }
+            other => panic!("expected AlreadySent, got {other:?}"),
+        }
+
+        let options = RunOptions {
+            throttled: true,
+            ..Default::default()
+        };
+        match run_backup_with(&backup, &options)? {
+            BackupOutcome::Skipped(reason @ SkipReason::Throttled(_)) => {
+                assert_eq!(reason.as_str(), "throttled");
+                assert!(!reason.is_stale());
+            }
+            other => panic!("expected Throttled, got {other:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_backup_from_manifest() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("latest.sql.gz"), "dump")?;
+        fs::write(temp_dir.path().join("newer.sql"), "dump")?;
+        let mut backup = backup_for(&temp_dir, String::new());
+        backup.manifest_file = Some("MANIFEST.json".to_string());
+
+        assert!(matches!(
+            run_backup(&backup, true)?,
+            BackupOutcome::Skipped(SkipReason::NoMatch)
+        ));
+
+        fs::write(
+            temp_dir.path().join("MANIFEST.json"),
+            r#"{"file": "latest.sql.gz", "version": "2"}"#,
+        )?;
+        match run_backup(&backup, true)? {
+            BackupOutcome::Skipped(SkipReason::DryRun { file, .. }) => {
+                assert_eq!(file.path, temp_dir.path().join("latest.sql.gz"));
+                assert_eq!(file.version.as_deref(), Some("2"));
+            }
+            other => panic!("expected DryRun, got {other:?}"),
+        }
+
+        let already_sent = |file: &SelectedFile| file.version.as_deref() == Some("2");
+        let options = RunOptions {
+            already_sent: Some(&already_sent),
+            ..Default::default()
+        };
+        assert!(matches!(
+            run_backup_with(&backup, &options)?,
+            BackupOutcome::Skipped(SkipReason::AlreadySent(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_backup_max_file_age() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("db.sql");
+        let modified = SystemTime::now() - Duration::from_secs(40 * 86_400);
+        fs::File::create(&path)?.set_modified(modified)?;
+        let backup = backup_for(&temp_dir, String::new());
+
+        let options = RunOptions {
+            dry_run: true,
+            max_file_age: Some(chrono::Duration::days(30)),
+            ..Default::default()
+        };
+        let error = run_backup_with(&backup, &options).unwrap_err().to_string();
+        assert!(
+            error.contains("is 40d old, older than --max-file-age 30d"),
+            "{error}"
+        );
+
+        let options = RunOptions {
+            max_file_age: Some(chrono::Duration::days(60)),
+            ..options
+        };
+        assert!(matches!(
+            run_backup_with(&backup, &options)?,
+            BackupOutcome::Skipped(SkipReason::DryRun { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_backup_notify_on_missing() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/api/webhooks/test")
+            .match_header("content-type", "application/json")
+            .match_body(mockito::Matcher::Regex(
+                "No recent backup found for db".to_string(),
+            ))
+            .with_status(204)
+            .expect(1)
+            .create();
+        let backup = BackupConfig {
+            notify_on_missing: true,
+            ..backup_for(&temp_dir, format!("{}/api/webhooks/test", server.url()))
+        };
+
+        // Dry runs never post
+        assert!(matches!(
+            run_backup(&backup, true)?,
+            BackupOutcome::Skipped(SkipReason::NoMatch)
+        ));
+        assert!(matches!(
+            run_backup(&backup, false)?,
+            BackupOutcome::Skipped(SkipReason::NoMatch)
+        ));
+        mock.assert();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_backup_posts_small_text_inline() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("db.sql"), "SELECT 1;\n")?;
+
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/api/webhooks/test")
+            .match_header("content-type", "application/json")
+            .match_body(mockito::Matcher::Regex(
+                r"```\\nSELECT 1;\\n```".to_string(),
+            ))
+            .with_status(204)
+            .expect(1)
+            .create();
+        let backup = BackupConfig {
+            inline_if_smaller_than: Some(1024),
+            ..backup_for(&temp_dir, format!("{}/api/webhooks/test", server.url()))
+        };
+
+        assert!(matches!(
+            run_backup(&backup, false)?,
+            BackupOutcome::Sent(_)
+        ));
+        mock.assert();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_backup_sends_bundle() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("db.sql"), "dump")?;
+        fs::write(temp_dir.path().join("users.sql"), "users")?;
+
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/api/webhooks/test")
+            .match_body(mockito::Matcher::Regex(
+                r#"filename="bundle-db-\d{4}-\d{2}-\d{2}\.zip""#.to_string(),
+            ))
+            .with_status(200)
+            .expect(1)
+            .create();
+        let backup = BackupConfig {
+            name: "bundle-db".to_string(),
+            bundle: true,
+            ..backup_for(&temp_dir, format!("{}/api/webhooks/test", server.url()))
+        };
+
+        let BackupOutcome::Sent(file) = run_backup(&backup, false)? else {
+            panic!("bundle was not sent");
+        };
+        mock.assert();
+        assert_eq!(file.path.extension().unwrap(), "zip");
+        // The archive only lives for the upload
+        assert!(!file.path.exists());
+
+        let BackupOutcome::Skipped(SkipReason::DryRun { file: again, .. }) =
+            run_backup(&backup, true)?
+        else {
+            panic!("expected a dry run");
+        };
+        assert_ne!(again.path, file.path);
+        assert_eq!(again.version, file.version);
+
+        let too_small = BackupConfig {
+            max_file_size: Some(10),
+            ..backup
+        };
+        let error = run_backup(&too_small, false).unwrap_err();
+        assert!(matches!(error.kind(), SenderError::FileTooLarge { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_content() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let selected = |name: &str, contents: &[u8]| -> Result<SelectedFile> {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, contents)?;
+            Ok(SelectedFile {
+                path,
+                modified: Local::now(),
+                size: contents.len() as u64,
+                version: None,
+            })
+        };
+
+        let text = selected("small.sql", b"SELECT 1;\n")?;
+        assert_eq!(
+            inline_content(&text, 1024, "Latest backup", None, UTF_8)?.as_deref(),
+            Some("Latest backup\n```\nSELECT 1;\n```")
+        );
+        assert_eq!(
+            inline_content(&text, 1024, "Latest backup", Some("@here"), UTF_8)?.as_deref(),
+            Some("@here Latest backup\n```\nSELECT 1;\n```")
+        );
+        // At or over the threshold, the file is attached
+        assert_eq!(
+            inline_content(&text, 10, "Latest backup", None, UTF_8)?,
+            None
+        );
+
+        // Binary files always attach
+        let binary = selected("small.gz", &[0x1f, 0x8b, 0x08, 0x00, 0xff])?;
+        assert_eq!(
+            inline_content(&binary, 1024, "Latest backup", None, UTF_8)?,
+            None
+        );
+        let nul = selected("nul.sql", b"a\0b")?;
+        assert_eq!(
+            inline_content(&nul, 1024, "Latest backup", None, UTF_8)?,
+            None
+        );
+
+        // Text that would push the message over Discord's limit attaches too
+        let long = selected("long.sql", "x".repeat(1990).as_bytes())?;
+        assert_eq!(
+            inline_content(&long, 4096, "Latest backup", None, UTF_8)?,
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_content_encoding() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let selected = |name: &str, contents: &[u8]| -> Result<SelectedFile> {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, contents)?;
+            Ok(SelectedFile {
+                path,
+                modified: Local::now(),
+                size: contents.len() as u64,
+                version: None,
+            })
+        };
+
+        // "café" in latin1 is not valid UTF-8
+        let latin1 = selected("status.log", b"caf\xe9")?;
+        assert_eq!(inline_content(&latin1, 1024, "Status", None, UTF_8)?, None);
+        assert_eq!(
+            inline_content(&latin1, 1024, "Status", None, WINDOWS_1252)?.as_deref(),
+            Some("Status\n```\ncafé\n```")
+        );
+
+        // A byte order mark wins over the configured encoding
+        let utf16: Vec<u8> = [0xff, 0xfe]
+            .into_iter()
+            .chain("ok".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let utf16 = selected("utf16.log", &utf16)?;
+        assert_eq!(
+            inline_content(&utf16, 1024, "Status", None, UTF_8)?.as_deref(),
+            Some("Status\n```\nok\n```")
+        );
+
+        // Bytes that aren't valid in the declared encoding are attached
+        let broken = selected("broken.log", &[0x61, 0x00, 0x00, 0xd8])?;
+        assert_eq!(
+            inline_content(&broken, 1024, "Status", None, UTF_16LE)?,
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_message() {
+        let backup = BackupConfig {
+            name: "db".to_string(),
+            check_period: Some("1d".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            missing_message(&backup, &SkipReason::NoMatch),
+            "⚠️ No recent backup found for db"
+        );
+        let stale = SkipReason::Stale {
+            latest: PathBuf::from("/backups/db.sql"),
+        };
+        assert_eq!(
+            missing_message(&backup, &stale),
+            "⚠️ No recent backup found for db (latest file db.sql is older than 1d)"
+        );
+    }
+
+    #[test]
+    fn test_run_backup_missing_directory_is_an_error() {
+        let backup = BackupConfig {
+            source_directory: "/nonexistent/latest-sender".into(),
+            file_pattern: Patterns::Single("*".to_string()),
+            ..Default::default()
+        };
+        let error = run_backup(&backup, true).unwrap_err();
+        assert!(format!("{error:#}").contains("does not exist"));
+        assert!(matches!(error.kind(), SenderError::DirectoryMissing(_)));
+    }
+
+    #[test]
+    fn test_embed_fields() {
+        let mtime = Local.with_ymd_and_hms(2024, 1, 15, 3, 0, 0).unwrap();
+        let context = MessageContext {
+            name: "db",
+            filename: "db.sql.gz",
+            size: 1536,
+            mtime,
+        };
+
+        let fields = embed_fields(&context);
+        let values: Vec<_> = fields
+            .iter()
+            .map(|f| (f.name.as_str(), f.value.as_str()))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("File", "db.sql.gz"),
+                ("Size", "1.5 KiB"),
+                ("Modified", "2024-01-15 03:00:00")
+            ]
+        );
+        assert!(fields.iter().all(|f| f.inline));
+    }
+
+    #[test]
+    fn test_latest_candidate() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let backup = BackupConfig {
+            check_period: Some("1h".to_string()),
+            ..backup_for(&temp_dir, String::new())
+        };
+        assert!(latest_candidate(&backup)?.is_none());
+
+        let path = temp_dir.path().join("db.sql");
+        fs::File::create(&path)?.set_modified(SystemTime::now() - Duration::from_secs(7200))?;
+        let (file, within_period) = latest_candidate(&backup)?.expect("a file");
+        assert_eq!(file.path, path);
+        assert!(!within_period);
+
+        let wider = BackupConfig {
+            check_period: Some("1d".to_string()),
+            ..backup
+        };
+        assert!(latest_candidate(&wider)?.is_some_and(|(_, within)| within));
+
+        Ok(())
+    }
+
+    /// Records what it was asked to send instead of uploading.
+    #[derive(Default)]
+    struct RecordingSender {
+        sent: std::sync::Mutex<Vec<(PathBuf, Option<String>)>>,
+        fail: bool,
+    }
+
+    impl FileSender for RecordingSender {
+        fn send(&self, path: &Path, message: Option<&str>) -> Result<()> {
+            self.sent
+                .lock()
+                .unwrap()
+                .push((path.to_path_buf(), message.map(str::to_string)));
+            if self.fail {
+                bail!("rejected");
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_upload_send_with_sender() -> Result<()> {
+        let upload = || Upload {
+            file: SelectedFile {
+                path: PathBuf::from("/backups/db.sql"),
+                modified: Local::now(),
+                size: 4,
+                version: None,
+            },
+            message: "Latest backup".to_string(),
+            send_options: SendOptions::default(),
+            target: Target::Discord,
+            inline: None,
+            _bundle: None,
+            delivered: Vec::new(),
+        };
+
+        let sender = RecordingSender::default();
+        let first = upload();
+        let result = first.send_with(&sender);
+        assert!(matches!(
+            first.finish(vec![("a".to_string(), result)]),
+            BackupOutcome::Sent(_)
+        ));
+        assert_eq!(
+            *sender.sent.lock().unwrap(),
+            vec![(
+                PathBuf::from("/backups/db.sql"),
+                Some("Latest backup".to_string())
+            )]
+        );
+
+        let failing = RecordingSender {
+            fail: true,
+            ..Default::default()
+        };
+        let second = upload();
+        let result = second.send_with(&failing);
+        match second.finish(vec![("a".to_string(), result)]) {
+            BackupOutcome::Failed { error, .. } => assert_eq!(error.to_string(), "rejected"),
+            other => panic!("expected Failed, got {other:?}"),
+        }
+
+        let third = upload();
+        let results = vec![
+            ("team".to_string(), third.send_with(&sender)),
+            ("archive".to_string(), third.send_with(&failing)),
+        ];
+        match third.finish(results) {
+            BackupOutcome::PartiallySent {
+                sent, total, error, ..
+            } => {
+                assert_eq!((sent, total), (1, 2));
+                assert_eq!(
+                    error.to_string(),
+                    "1 of 2 webhooks failed: archive: rejected"
+                );
+            }
+            other => panic!("expected PartiallySent, got {other:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_sender_requires_credentials() {
+        let backup = BackupConfig {
+            target: Some(Target::Slack),
+            ..Default::default()
+        };
+        let error = build_sender(&backup, &SendOptions::default())
+            .err()
+            .expect("slack_token is missing");
+        assert_eq!(
+            error.to_string(),
+            "slack_token is required for target \"slack\""
+        );
+
+        let backup = BackupConfig {
+            target: Some(Target::Telegram),
+            bot_token: Some("123:abc".to_string()),
+            chat_id: Some("1".to_string()),
+            ..Default::default()
+        };
+        assert!(build_sender(&backup, &SendOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_test_connectivity() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("db.sql"), "dump")?;
+
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/api/webhooks/test")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "content": "latest-sender connectivity test for db",
+                "allowed_mentions": { "parse": [] },
+            })))
+            .with_status(204)
+            .expect(1)
+            .create();
+        let backup = BackupConfig {
+            use_embed: true,
+            ..backup_for(&temp_dir, format!("{}/api/webhooks/test", server.url()))
+        };
+        test_connectivity(&backup)?;
+        mock.assert();
+
+        let slack = BackupConfig {
+            target: Some(Target::Slack),
+            ..backup
+        };
+        assert_eq!(
+            test_connectivity(&slack).unwrap_err().to_string(),
+            "connectivity test is not supported for Slack"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_with_open_retries() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let gone = temp_dir.path().join("db.sql.tmp");
+        let present = temp_dir.path().join("db.sql");
+        fs::write(&present, "dump")?;
+
+        // The first scan sees a temporary name that is renamed away before it is opened
+        let mut scans = vec![present.clone(), gone.clone()];
+        let found = find_with_open_retries(2, Duration::ZERO, || {
+            Ok(scans.pop().map(|path| (path, Local::now(), 4)))
+        })?;
+        assert_eq!(found.map(|(path, ..)| path), Some(present));
+        assert!(scans.is_empty());
+
+        let mut calls = 0;
+        let found = find_with_open_retries(2, Duration::ZERO, || {
+            calls += 1;
+            Ok(Some((gone.clone(), Local::now(), 4)))
+        })?;
+        assert_eq!(found.map(|(path, ..)| path), Some(gone));
+        assert_eq!(calls, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_destination_url() {
+        let discord = BackupConfig {
+            webhook_url: "https://discord.com/api/webhooks/1/token".into(),
+            ..Default::default()
+        };
+        assert_eq!(discord.webhook_url, destination_url(&discord));
+
+        let slack = BackupConfig {
+            target: Some(Target::Slack),
+            ..Default::default()
+        };
+        assert_eq!(destination_url(&slack), SLACK_FILES_UPLOAD_URL);
+
+        let telegram = BackupConfig {
+            target: Some(Target::Telegram),
+            ..Default::default()
+        };
+        assert_eq!(destination_url(&telegram), TELEGRAM_API_URL);
+    }
+}
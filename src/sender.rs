@@ -0,0 +1,10 @@
+use crate::error::Result;
+use std::path::Path;
+
+/// A destination that a backup's file can be uploaded to.
+///
+/// Implementations hold their own connection settings (webhook URL, tokens,
+/// channel), so callers only pass the file and the message.
+pub trait FileSender: Send + Sync {
+    fn send(&self, path: &Path, message: Option<&str>) -> Result<()>;
+}
@@ -0,0 +1,166 @@
+use crate::error::{bail, Context, Result, SenderError};
+use crate::sender::FileSender;
+use reqwest::blocking::multipart;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Slack Web API endpoint used when no override is configured.
+pub const SLACK_FILES_UPLOAD_URL: &str = "https://slack.com/api/files.upload";
+
+/// Body returned by the Slack Web API. Errors are reported with a 200 status
+/// and `ok: false`.
+#[derive(Debug, Deserialize)]
+struct SlackResponse {
+    ok: bool,
+    error: Option<String>,
+}
+
+/// Uploads to one Slack channel with a bot token.
+pub struct SlackSender {
+    api_url: String,
+    token: String,
+    channel: String,
+}
+
+impl FileSender for SlackSender {
+    fn send(&self, path: &Path, message: Option<&str>) -> Result<()> {
+        Self::send_file(&self.api_url, &self.token, &self.channel, path, message)
+    }
+}
+
+impl SlackSender {
+    pub fn new(api_url: &str, token: &str, channel: &str) -> Self {
+        Self {
+            api_url: api_url.to_string(),
+            token: token.to_string(),
+            channel: channel.to_string(),
+        }
+    }
+
+    pub fn send_file<P: AsRef<Path>>(
+        api_url: &str,
+        token: &str,
+        channel: &str,
+        file_path: P,
+        message: Option<&str>,
+    ) -> Result<()> {
+        let path = file_path.as_ref();
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .context("Failed to get file name")?;
+
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to open file: {path:?}"))?;
+
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)
+            .with_context(|| format!("Failed to read file: {path:?}"))?;
+
+        let mut form = multipart::Form::new()
+            .part(
+                "file",
+                multipart::Part::bytes(buffer).file_name(file_name.to_string()),
+            )
+            .text("filename", file_name.to_string())
+            .text("channels", channel.to_string());
+
+        if let Some(msg) = message {
+            form = form.text("initial_comment", msg.to_string());
+        }
+
+        let client = reqwest::blocking::Client::new();
+        let response = client
+            .post(api_url)
+            .bearer_auth(token)
+            .multipart(form)
+            .send()
+            .context("Failed to send request to Slack")?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .unwrap_or_else(|_| "No error message".to_string());
+
+        if !status.is_success() {
+            return Err(SenderError::HttpStatus {
+                service: "Slack",
+                code: status,
+                body,
+            });
+        }
+
+        let parsed: SlackResponse = serde_json::from_str(&body)
+            .with_context(|| format!("Unexpected response from Slack: {body}"))?;
+        if !parsed.ok {
+            bail!(
+                "Slack API returned error: {}",
+                parsed.error.as_deref().unwrap_or("unknown error")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_send_file_success() -> Result<()> {
+        let mut temp_file = NamedTempFile::new()?;
+        writeln!(temp_file, "Test content")?;
+
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/api/files.upload")
+            .match_header("authorization", "Bearer xoxb-test")
+            .match_body(Matcher::Regex("name=\"channels\"\r\n\r\nC123".to_string()))
+            .with_status(200)
+            .with_body(r#"{"ok": true}"#)
+            .create();
+
+        let api_url = format!("{}/api/files.upload", server.url());
+
+        SlackSender::send_file(
+            &api_url,
+            "xoxb-test",
+            "C123",
+            temp_file.path(),
+            Some("Test message"),
+        )?;
+
+        mock.assert();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_file_slack_error() -> Result<()> {
+        let mut temp_file = NamedTempFile::new()?;
+        writeln!(temp_file, "Test content")?;
+
+        let mut server = Server::new();
+        let _m = server
+            .mock("POST", "/api/files.upload")
+            .with_status(200)
+            .with_body(r#"{"ok": false, "error": "channel_not_found"}"#)
+            .create();
+
+        let api_url = format!("{}/api/files.upload", server.url());
+
+        let result = SlackSender::send_file(&api_url, "xoxb-test", "C404", temp_file.path(), None);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Slack API returned error: channel_not_found"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,470 @@
+use crate::checksum::sha256_str;
+use crate::error::{Context, Result, SenderError};
+use chrono::{DateTime, Duration, Local};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Record of files already sent, persisted between runs as JSON.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    /// Last sent file, keyed by backup name.
+    #[serde(default)]
+    pub backups: BTreeMap<String, SentRecord>,
+    /// File that only some webhooks accepted, keyed by backup name. Cleared
+    /// once the file reaches every webhook.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub partial: BTreeMap<String, PartialRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SentRecord {
+    pub path: PathBuf,
+    pub modified: DateTime<Local>,
+    /// When the upload happened; used by `once_per`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sent_at: Option<DateTime<Local>>,
+    /// Version from the backup's `manifest_file` or `bundle`, when it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// SHA-256 of the file, recorded with `dedup = "hash"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// Webhooks that accepted a file which didn't reach all of them, so later
+/// runs only retry the others.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartialRecord {
+    pub path: PathBuf,
+    pub modified: DateTime<Local>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// SHA-256 of each webhook URL, since the URLs contain their tokens.
+    pub webhooks: BTreeSet<String>,
+}
+
+impl PartialRecord {
+    /// True if the record is about this file: the same manifest or bundle
+    /// version when there is one, otherwise the same path and modified time.
+    fn is_for(&self, path: &Path, modified: DateTime<Local>, version: Option<&str>) -> bool {
+        match version {
+            Some(version) => self.version.as_deref() == Some(version),
+            None => self.version.is_none() && self.path == path && self.modified == modified,
+        }
+    }
+}
+
+/// How a file is recognized as already sent (`dedup`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dedup {
+    /// Same path as the last file sent, whatever its contents.
+    Path,
+    /// Same path and modified time.
+    #[default]
+    Mtime,
+    /// Same contents (SHA-256), even if the mtime or path changed.
+    Hash,
+    /// Never skip.
+    Off,
+}
+
+impl State {
+    /// Loads the state file, returning an empty state if it doesn't exist yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read state file: {path:?}"))?;
+        let state = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse state file: {path:?}"))?;
+        Ok(state)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let content = serde_json::to_string_pretty(self).context("Failed to serialize state")?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write state file: {path:?}"))?;
+        Ok(())
+    }
+
+    /// Returns true if `path` with the given modified time was the last file sent for `backup`.
+    pub fn is_already_sent(&self, backup: &str, path: &Path, modified: DateTime<Local>) -> bool {
+        self.backups
+            .get(backup)
+            .is_some_and(|record| record.path == path && record.modified == modified)
+    }
+
+    pub fn record_sent(&mut self, backup: &str, path: &Path, modified: DateTime<Local>) {
+        self.partial.remove(backup);
+        self.backups.insert(
+            backup.to_string(),
+            SentRecord {
+                path: path.to_path_buf(),
+                modified,
+                sent_at: Some(Local::now()),
+                version: None,
+                hash: None,
+            },
+        );
+    }
+
+    /// Records that the webhooks at `urls` accepted the file, adding to the
+    /// webhooks already recorded for the same file.
+    pub fn record_delivered(
+        &mut self,
+        backup: &str,
+        path: &Path,
+        modified: DateTime<Local>,
+        version: Option<&str>,
+        urls: &[String],
+    ) {
+        let record = PartialRecord {
+            path: path.to_path_buf(),
+            modified,
+            version: version.map(str::to_string),
+            webhooks: BTreeSet::new(),
+        };
+        let entry = self
+            .partial
+            .entry(backup.to_string())
+            .or_insert(record.clone());
+        if !entry.is_for(path, modified, version) {
+            *entry = record;
+        }
+        entry
+            .webhooks
+            .extend(urls.iter().map(|url| sha256_str(url)));
+    }
+
+    /// Returns true if the webhook at `url` already accepted the file in a
+    /// partially sent run.
+    pub fn is_delivered(
+        &self,
+        backup: &str,
+        path: &Path,
+        modified: DateTime<Local>,
+        version: Option<&str>,
+        url: &str,
+    ) -> bool {
+        self.partial.get(backup).is_some_and(|record| {
+            record.is_for(path, modified, version) && record.webhooks.contains(&sha256_str(url))
+        })
+    }
+
+    /// Stores the manifest version of the file just recorded with [`Self::record_sent`].
+    pub fn record_version(&mut self, backup: &str, version: &str) {
+        if let Some(record) = self.backups.get_mut(backup) {
+            record.version = Some(version.to_string());
+        }
+    }
+
+    /// Stores the SHA-256 of the file just recorded with [`Self::record_sent`].
+    pub fn record_hash(&mut self, backup: &str, hash: &str) {
+        if let Some(record) = self.backups.get_mut(backup) {
+            record.hash = Some(hash.to_string());
+        }
+    }
+
+    /// Returns true if the file counts as already sent for `backup` under
+    /// `dedup`. `hash` is the file's SHA-256, needed only for [`Dedup::Hash`];
+    /// without it the file is never a duplicate.
+    pub fn is_duplicate(
+        &self,
+        backup: &str,
+        dedup: Dedup,
+        path: &Path,
+        modified: DateTime<Local>,
+        hash: Option<&str>,
+    ) -> bool {
+        let Some(record) = self.backups.get(backup) else {
+            return false;
+        };
+        match dedup {
+            Dedup::Path => record.path == path,
+            Dedup::Mtime => self.is_already_sent(backup, path, modified),
+            Dedup::Hash => hash.is_some() && record.hash.as_deref() == hash,
+            Dedup::Off => false,
+        }
+    }
+
+    /// Returns true if the last file sent for `backup` had manifest `version`.
+    pub fn is_version_sent(&self, backup: &str, version: &str) -> bool {
+        self.backups
+            .get(backup)
+            .is_some_and(|record| record.version.as_deref() == Some(version))
+    }
+
+    /// Returns true if `backup` was sent less than `interval` before `now`.
+    pub fn is_throttled(&self, backup: &str, interval: Duration, now: DateTime<Local>) -> bool {
+        self.backups
+            .get(backup)
+            .and_then(|record| record.sent_at)
+            .is_some_and(|sent_at| now < sent_at + interval)
+    }
+}
+
+/// Exclusive advisory lock next to a state file (`<state file>.lock`), so
+/// overlapping runs don't overwrite each other's records. Released when
+/// dropped, including while unwinding from a panic, and by the OS when the
+/// process exits.
+#[derive(Debug)]
+pub struct StateLock {
+    file: File,
+}
+
+impl StateLock {
+    /// Locks the state file at `state_path`. With `wait`, blocks until the
+    /// lock is free; otherwise fails with [`SenderError::AlreadyRunning`]
+    /// while another instance holds it.
+    pub fn acquire<P: AsRef<Path>>(state_path: P, wait: bool) -> Result<Self> {
+        let mut path = state_path.as_ref().as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+
+        let file =
+            File::create(&path).with_context(|| format!("Failed to open lock file: {path:?}"))?;
+        if wait {
+            file.lock_exclusive()
+                .with_context(|| format!("Failed to lock {path:?}"))?;
+        } else if let Err(e) = file.try_lock_exclusive() {
+            if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() {
+                return Err(SenderError::AlreadyRunning(path));
+            }
+            return Err(e).with_context(|| format!("Failed to lock {path:?}"));
+        }
+        Ok(Self { file })
+    }
+}
+
+impl Drop for StateLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_missing_state_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+
+        let state = State::load(temp_dir.path().join("state.json"))?;
+        assert!(state.backups.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let state_path = temp_dir.path().join("state.json");
+        let modified = Local::now();
+
+        let mut state = State::default();
+        state.record_sent("db", Path::new("/backups/db.sql.gz"), modified);
+        state.save(&state_path)?;
+
+        let loaded = State::load(&state_path)?;
+        assert_eq!(loaded, state);
+        assert!(loaded.is_already_sent("db", Path::new("/backups/db.sql.gz"), modified));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_already_sent() {
+        let modified = Local::now();
+        let mut state = State::default();
+        state.record_sent("db", Path::new("/backups/a.sql"), modified);
+
+        assert!(state.is_already_sent("db", Path::new("/backups/a.sql"), modified));
+        assert!(!state.is_already_sent("db", Path::new("/backups/b.sql"), modified));
+        assert!(!state.is_already_sent(
+            "db",
+            Path::new("/backups/a.sql"),
+            modified + Duration::seconds(1)
+        ));
+        assert!(!state.is_already_sent("logs", Path::new("/backups/a.sql"), modified));
+    }
+
+    #[test]
+    fn test_is_throttled_window_boundary() {
+        let mut state = State::default();
+        assert!(!state.is_throttled("db", Duration::days(1), Local::now()));
+
+        state.record_sent("db", Path::new("/backups/a.sql"), Local::now());
+        let sent_at = state.backups["db"].sent_at.expect("sent_at is recorded");
+        let day = Duration::days(1);
+
+        assert!(state.is_throttled("db", day, sent_at));
+        assert!(state.is_throttled("db", day, sent_at + day - Duration::seconds(1)));
+        assert!(!state.is_throttled("db", day, sent_at + day));
+        assert!(!state.is_throttled("logs", day, sent_at));
+    }
+
+    #[test]
+    fn test_load_state_without_sent_at() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let state_path = temp_dir.path().join("state.json");
+        fs::write(
+            &state_path,
+            r#"{"backups": {"db": {"path": "/backups/a.sql", "modified": "2024-01-15T09:30:00+00:00"}}}"#,
+        )?;
+
+        let state = State::load(&state_path)?;
+        assert_eq!(state.backups["db"].sent_at, None);
+        assert!(!state.is_throttled("db", Duration::days(1), Local::now()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_version_sent() {
+        let modified = Local::now();
+        let mut state = State::default();
+        assert!(!state.is_version_sent("db", "3f2a"));
+
+        state.record_sent("db", Path::new("/backups/latest.sql.gz"), modified);
+        assert!(!state.is_version_sent("db", "3f2a"));
+
+        state.record_version("db", "3f2a");
+        assert!(state.is_version_sent("db", "3f2a"));
+        assert!(!state.is_version_sent("db", "9c1b"));
+        assert!(!state.is_version_sent("logs", "3f2a"));
+    }
+
+    #[test]
+    fn test_record_delivered() {
+        let modified = Local::now();
+        let path = Path::new("/backups/db.sql");
+        let team = "https://discord.com/api/webhooks/1/team".to_string();
+        let archive = "https://discord.com/api/webhooks/2/archive".to_string();
+        let mut state = State::default();
+
+        state.record_delivered("db", path, modified, None, std::slice::from_ref(&team));
+        assert!(state.is_delivered("db", path, modified, None, &team));
+        assert!(!state.is_delivered("db", path, modified, None, &archive));
+        assert!(!state.is_delivered("db", path, modified + Duration::seconds(1), None, &team));
+        assert!(!state.partial["db"]
+            .webhooks
+            .iter()
+            .any(|w| w.contains("team")));
+
+        state.record_delivered("db", path, modified, None, std::slice::from_ref(&archive));
+        assert!(state.is_delivered("db", path, modified, None, &team));
+        assert!(state.is_delivered("db", path, modified, None, &archive));
+
+        // A newer file starts over
+        let newer = modified + Duration::hours(1);
+        state.record_delivered("db", path, newer, None, std::slice::from_ref(&archive));
+        assert!(!state.is_delivered("db", path, newer, None, &team));
+        assert!(state.is_delivered("db", path, newer, None, &archive));
+
+        state.record_sent("db", path, newer);
+        assert!(state.partial.is_empty());
+    }
+
+    #[test]
+    fn test_state_lock_contention() -> Result<()> {
+        use std::sync::mpsc;
+        use std::thread;
+
+        let temp_dir = TempDir::new()?;
+        let state_path = temp_dir.path().join("state.json");
+
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let holder = {
+            let state_path = state_path.clone();
+            thread::spawn(move || -> Result<()> {
+                let _lock = StateLock::acquire(&state_path, false)?;
+                locked_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                Ok(())
+            })
+        };
+        locked_rx.recv().unwrap();
+
+        let error = StateLock::acquire(&state_path, false).unwrap_err();
+        assert!(matches!(error.kind(), SenderError::AlreadyRunning(_)));
+        assert!(error.to_string().starts_with("another instance is running"));
+
+        // A waiting instance gets the lock once the first one lets go
+        let waiter = {
+            let state_path = state_path.clone();
+            thread::spawn(move || StateLock::acquire(&state_path, true).map(drop))
+        };
+        release_tx.send(()).unwrap();
+        holder.join().unwrap()?;
+        waiter.join().unwrap()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_lock_released_on_panic() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let state_path = temp_dir.path().join("state.json");
+
+        let path = state_path.clone();
+        let result = std::thread::spawn(move || {
+            let _lock = StateLock::acquire(&path, false).unwrap();
+            panic!("backup run crashed");
+        })
+        .join();
+        assert!(result.is_err());
+
+        StateLock::acquire(&state_path, false)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_duplicate_by_hash() {
+        let modified = Local::now();
+        let path = Path::new("/backups/db.sql");
+        let mut state = State::default();
+        state.record_sent("db", path, modified);
+        state.record_hash("db", "aaa");
+
+        // A verification pass bumped the mtime but the content is the same
+        let bumped = modified + Duration::minutes(5);
+        assert!(state.is_duplicate("db", Dedup::Hash, path, bumped, Some("aaa")));
+        assert!(!state.is_duplicate("db", Dedup::Mtime, path, bumped, Some("aaa")));
+
+        // New content is sent even with the old mtime
+        assert!(!state.is_duplicate("db", Dedup::Hash, path, modified, Some("bbb")));
+        assert!(!state.is_duplicate("db", Dedup::Hash, path, modified, None));
+        assert!(!state.is_duplicate("logs", Dedup::Hash, path, modified, Some("aaa")));
+    }
+
+    #[test]
+    fn test_is_duplicate_by_path_and_off() {
+        let modified = Local::now();
+        let path = Path::new("/backups/db.sql");
+        let mut state = State::default();
+        state.record_sent("db", path, modified);
+
+        let later = modified + Duration::hours(1);
+        assert!(state.is_duplicate("db", Dedup::Path, path, later, None));
+        assert!(!state.is_duplicate(
+            "db",
+            Dedup::Path,
+            Path::new("/backups/new.sql"),
+            later,
+            None
+        ));
+        assert!(state.is_duplicate("db", Dedup::Mtime, path, modified, None));
+        assert!(!state.is_duplicate("db", Dedup::Off, path, modified, None));
+    }
+}
@@ -0,0 +1,198 @@
+use crate::error::{Context, Result, SenderError};
+use crate::sender::FileSender;
+use reqwest::blocking::multipart;
+use serde::Deserialize;
+use std::fs::{self, File};
+use std::path::Path;
+
+/// Bot API base URL used when no override is configured.
+pub const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+
+/// Telegram's upload limit for files sent by bots.
+pub const TELEGRAM_MAX_FILE_SIZE: u64 = 50 * 1024 * 1024;
+
+/// Body returned by the Bot API. Failures carry `ok: false` and a description.
+#[derive(Debug, Deserialize)]
+struct TelegramResponse {
+    ok: bool,
+    description: Option<String>,
+}
+
+/// Uploads to one Telegram chat with a bot token.
+pub struct TelegramSender {
+    api_url: String,
+    bot_token: String,
+    chat_id: String,
+}
+
+impl FileSender for TelegramSender {
+    fn send(&self, path: &Path, message: Option<&str>) -> Result<()> {
+        Self::send_file(&self.api_url, &self.bot_token, &self.chat_id, path, message)
+    }
+}
+
+impl TelegramSender {
+    pub fn new(api_url: &str, bot_token: &str, chat_id: &str) -> Self {
+        Self {
+            api_url: api_url.to_string(),
+            bot_token: bot_token.to_string(),
+            chat_id: chat_id.to_string(),
+        }
+    }
+
+    /// Uploads the file to `chat_id` with `sendDocument`. `api_url` is the
+    /// Bot API base URL, normally [`TELEGRAM_API_URL`].
+    pub fn send_file<P: AsRef<Path>>(
+        api_url: &str,
+        bot_token: &str,
+        chat_id: &str,
+        file_path: P,
+        caption: Option<&str>,
+    ) -> Result<()> {
+        let path = file_path.as_ref();
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .context("Failed to get file name")?;
+
+        let size = fs::metadata(path)
+            .with_context(|| format!("Failed to read metadata of {path:?}"))?
+            .len();
+        if size > TELEGRAM_MAX_FILE_SIZE {
+            return Err(SenderError::FileTooLarge {
+                service: "Telegram",
+                path: path.to_path_buf(),
+                size,
+                limit: TELEGRAM_MAX_FILE_SIZE,
+            });
+        }
+
+        let file = File::open(path).with_context(|| format!("Failed to open file: {path:?}"))?;
+
+        let mut form = multipart::Form::new()
+            .text("chat_id", chat_id.to_string())
+            .part(
+                "document",
+                multipart::Part::reader_with_length(file, size).file_name(file_name.to_string()),
+            );
+
+        if let Some(caption) = caption {
+            form = form.text("caption", caption.to_string());
+        }
+
+        let url = format!(
+            "{}/bot{bot_token}/sendDocument",
+            api_url.trim_end_matches('/')
+        );
+        let client = reqwest::blocking::Client::new();
+        // The URL contains the token, so it's left out of the error
+        let response = client
+            .post(url)
+            .multipart(form)
+            .send()
+            .map_err(|e| SenderError::Network(e.without_url()))
+            .context("Failed to send request to Telegram")?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .unwrap_or_else(|_| "No error message".to_string());
+
+        match serde_json::from_str::<TelegramResponse>(&body) {
+            Ok(parsed) if parsed.ok && status.is_success() => Ok(()),
+            Ok(parsed) => Err(SenderError::HttpStatus {
+                service: "Telegram",
+                code: status,
+                body: parsed
+                    .description
+                    .unwrap_or_else(|| "unknown error".to_string()),
+            }),
+            Err(_) => Err(SenderError::HttpStatus {
+                service: "Telegram",
+                code: status,
+                body,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_send_file_success() -> Result<()> {
+        let mut temp_file = NamedTempFile::new()?;
+        writeln!(temp_file, "Test content")?;
+
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/bot123:abc/sendDocument")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex("name=\"chat_id\"\r\n\r\n-100123".to_string()),
+                Matcher::Regex("name=\"caption\"\r\n\r\nTest message".to_string()),
+                Matcher::Regex("name=\"document\"; filename=".to_string()),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"ok": true, "result": {}}"#)
+            .create();
+
+        TelegramSender::send_file(
+            &server.url(),
+            "123:abc",
+            "-100123",
+            temp_file.path(),
+            Some("Test message"),
+        )?;
+
+        mock.assert();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_file_telegram_error() -> Result<()> {
+        let mut temp_file = NamedTempFile::new()?;
+        writeln!(temp_file, "Test content")?;
+
+        let mut server = Server::new();
+        let _m = server
+            .mock("POST", "/bot123:abc/sendDocument")
+            .with_status(400)
+            .with_body(
+                r#"{"ok": false, "error_code": 400, "description": "Bad Request: chat not found"}"#,
+            )
+            .create();
+
+        let error =
+            TelegramSender::send_file(&server.url(), "123:abc", "1", temp_file.path(), None)
+                .unwrap_err()
+                .to_string();
+        assert_eq!(
+            error,
+            "Telegram API returned error: 400 Bad Request - Bad Request: chat not found"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_file_rejects_oversized_file() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        temp_file.as_file().set_len(TELEGRAM_MAX_FILE_SIZE + 1)?;
+
+        let error =
+            TelegramSender::send_file("http://127.0.0.1:1", "123:abc", "1", temp_file.path(), None)
+                .unwrap_err()
+                .to_string();
+        assert!(
+            error.contains("exceeds Telegram limit of 50.0 MiB"),
+            "{error}"
+        );
+
+        Ok(())
+    }
+}
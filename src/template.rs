@@ -0,0 +1,191 @@
+use crate::error::{Result, SenderError};
+use crate::humanize::format_bytes;
+use chrono::{DateTime, Local};
+
+/// Message used when a backup has no `message_template`.
+pub const DEFAULT_MESSAGE_TEMPLATE: &str = "Latest backup from: {name}";
+
+/// Placeholders available in `message_template`.
+pub const MESSAGE_PLACEHOLDERS: [&str; 4] = ["name", "filename", "size", "mtime"];
+
+/// Placeholders available in `upload_filename`.
+pub const UPLOAD_FILENAME_PLACEHOLDERS: [&str; 1] = ["date"];
+
+/// Values substituted into a message template.
+#[derive(Debug, Clone)]
+pub struct MessageContext<'a> {
+    pub name: &'a str,
+    pub filename: &'a str,
+    pub size: u64,
+    pub mtime: DateTime<Local>,
+}
+
+impl MessageContext<'_> {
+    fn lookup(&self, placeholder: &str) -> Option<String> {
+        match placeholder {
+            "name" => Some(self.name.to_string()),
+            "filename" => Some(self.filename.to_string()),
+            "size" => Some(format_bytes(self.size)),
+            "mtime" => Some(self.mtime.format("%Y-%m-%d %H:%M:%S").to_string()),
+            _ => None,
+        }
+    }
+}
+
+pub fn render_message(template: &str, context: &MessageContext) -> Result<String> {
+    render(template, |placeholder| context.lookup(placeholder))
+}
+
+/// Renders an `upload_filename` template; `{date}` is the file's modified
+/// date as `YYYY-MM-DD`.
+pub fn render_upload_filename(template: &str, mtime: DateTime<Local>) -> Result<String> {
+    render(template, |placeholder| {
+        (placeholder == "date").then(|| mtime.format("%Y-%m-%d").to_string())
+    })
+}
+
+/// Renders a `source_directory` or `file_pattern`: `{name}` is the backup's
+/// name and `{date}` is `today` as `YYYY-MM-DD`. Brace groups with a comma,
+/// such as `{sql,gz}`, are glob alternatives and are kept as they are.
+pub fn render_path(template: &str, name: &str, today: DateTime<Local>) -> Result<String> {
+    render(template, |placeholder| match placeholder {
+        "name" => Some(name.to_string()),
+        "date" => Some(today.format("%Y-%m-%d").to_string()),
+        alternatives if alternatives.contains(',') => Some(format!("{{{alternatives}}}")),
+        _ => None,
+    })
+    .map_err(|e| SenderError::ConfigInvalid(format!("{e}; use {{name}} or {{date}}")))
+}
+
+/// Checks that `template` only uses placeholders from `placeholders`.
+pub fn validate_template(template: &str, placeholders: &[&str]) -> Result<()> {
+    render(template, |placeholder| {
+        placeholders.contains(&placeholder).then(String::new)
+    })
+    .map(|_| ())
+}
+
+/// Substitutes `{placeholder}`s in `template` using `lookup`. `{{` and `}}`
+/// produce literal braces.
+pub fn render<F>(template: &str, lookup: F) -> Result<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut rendered = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                rendered.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                rendered.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => {
+                            return Err(SenderError::ConfigInvalid(format!(
+                                "unclosed '{{' in template '{template}'"
+                            )))
+                        }
+                    }
+                }
+                let value = lookup(&placeholder).ok_or_else(|| {
+                    SenderError::ConfigInvalid(format!(
+                        "unknown placeholder '{{{placeholder}}}' in template '{template}'"
+                    ))
+                })?;
+                rendered.push_str(&value);
+            }
+            c => rendered.push(c),
+        }
+    }
+
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn context() -> MessageContext<'static> {
+        MessageContext {
+            name: "prod",
+            filename: "db.sql.gz",
+            size: 2048,
+            mtime: Local.with_ymd_and_hms(2024, 1, 15, 3, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_render_message() -> Result<()> {
+        assert_eq!(
+            render_message(DEFAULT_MESSAGE_TEMPLATE, &context())?,
+            "Latest backup from: prod"
+        );
+        assert_eq!(
+            render_message("{name}: {filename} ({size}, {mtime}) {{raw}}", &context())?,
+            "prod: db.sql.gz (2.0 KiB, 2024-01-15 03:00:00) {raw}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_message_errors() {
+        let error = render_message("{nmae}", &context()).unwrap_err();
+        assert!(error.to_string().contains("unknown placeholder '{nmae}'"));
+
+        assert!(render_message("{name", &context()).is_err());
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert!(validate_template("{name} {mtime}", &MESSAGE_PLACEHOLDERS).is_ok());
+        assert!(validate_template("{checksum}", &MESSAGE_PLACEHOLDERS).is_err());
+    }
+
+    #[test]
+    fn test_render_upload_filename() -> Result<()> {
+        let mtime = context().mtime;
+        assert_eq!(
+            render_upload_filename("prod-backup-{date}.sql.gz", mtime)?,
+            "prod-backup-2024-01-15.sql.gz"
+        );
+        assert_eq!(
+            render_upload_filename("prod-backup.sql.gz", mtime)?,
+            "prod-backup.sql.gz"
+        );
+        assert!(render_upload_filename("{name}.sql", mtime).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_path() -> Result<()> {
+        let today = context().mtime;
+        assert_eq!(
+            render_path("/srv/backups/{name}/{date}", "prod", today)?,
+            "/srv/backups/prod/2024-01-15"
+        );
+        assert_eq!(
+            render_path("{name}-*.{sql,sql.gz}", "prod", today)?,
+            "prod-*.{sql,sql.gz}"
+        );
+        assert_eq!(render_path("*.sql", "prod", today)?, "*.sql");
+
+        let error = render_path("/srv/{host}", "prod", today).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown placeholder '{host}' in template '/srv/{host}'; use {name} or {date}"
+        );
+        Ok(())
+    }
+}